@@ -0,0 +1,137 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+declare_id!("29NXxyJ8vtSoNvrgJsw7KHuQaGvDVugLc2xG9fbbvFSQ");
+
+//repay modes, first byte of the callback data
+pub const REPAY_IN_INPUT: u8 = 0;
+pub const REPAY_IN_OUTPUT: u8 = 1;
+
+//the pool fee tier is in basis points and charged on what comes back
+const FEE_DENOMINATOR: u128 = 10_000;
+
+#[program]
+pub mod flash_swap_callback {
+    use super::*;
+
+    //called by spool-amm after the output tokens were sent, the reserves are the stored
+    //pool reserves before the swap
+    pub fn flash_swap_callback(
+        ctx: Context<FlashSwapCallback>,
+        amount_out: u64,
+        fee_tier_bps: u16,
+        input_reserve: u64,
+        output_reserve: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let mode = *data.first().ok_or(CallbackErrors::MissingMode)?;
+        let pool = PoolPricing {
+            fee_numerator: fee_tier_bps as u128,
+            input_reserve: input_reserve as u128,
+            output_reserve: output_reserve as u128,
+        };
+        ctx.accounts.repay(&pool, amount_out, mode)?;
+        msg!("flash swap callback repaid");
+        Ok(())
+    }
+}
+
+//what the pool prices the repayment against
+pub struct PoolPricing {
+    pub fee_numerator: u128,
+    pub input_reserve: u128,
+    pub output_reserve: u128,
+}
+
+impl PoolPricing {
+    //input needed so that (in + in_paid * (1 - fee)) * (out - amount_out) >= in * out
+    fn input_repay_amount(&self, amount_out: u64) -> u64 {
+        let output_left = self.output_reserve - amount_out as u128;
+
+        //two extra units cover the rounding of the pool fee
+        let numerator = self.input_reserve * amount_out as u128 * FEE_DENOMINATOR;
+        let denominator = output_left * (FEE_DENOMINATOR - self.fee_numerator);
+        (numerator.div_ceil(denominator) + 2) as u64
+    }
+
+    //pay back the borrowed output plus the fee on it
+    fn output_repay_amount(&self, amount_out: u64) -> u64 {
+        let numerator = amount_out as u128 * FEE_DENOMINATOR;
+        (numerator.div_ceil(FEE_DENOMINATOR - self.fee_numerator) + 2) as u64
+    }
+}
+
+#[error_code]
+pub enum CallbackErrors {
+    #[msg("callback data has no repay mode")]
+    MissingMode,
+
+    #[msg("unknown repay mode")]
+    UnknownMode,
+}
+
+#[derive(Accounts)]
+pub struct FlashSwapCallback<'info> {
+    pub payer: Signer<'info>,
+
+    pub input_mint: InterfaceAccount<'info, Mint>,
+    pub output_mint: InterfaceAccount<'info, Mint>,
+
+    //payer accounts used for the repayment
+    #[account(mut, token::mint = input_mint, token::authority = payer)]
+    pub payer_input_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = output_mint, token::authority = payer)]
+    pub payer_output_account: InterfaceAccount<'info, TokenAccount>,
+
+    //pool vaults, output vault already paid out amount_out
+    #[account(mut, token::mint = input_mint)]
+    pub input_vault_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = output_mint)]
+    pub output_vault_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> FlashSwapCallback<'info> {
+    fn repay(&self, pool: &PoolPricing, amount_out: u64, mode: u8) -> Result<()> {
+        match mode {
+            REPAY_IN_INPUT => {
+                let amount = pool.input_repay_amount(amount_out);
+                self.transfer(
+                    &self.input_mint,
+                    &self.payer_input_account,
+                    &self.input_vault_account,
+                    amount,
+                )
+            }
+            REPAY_IN_OUTPUT => {
+                let amount = pool.output_repay_amount(amount_out);
+                self.transfer(
+                    &self.output_mint,
+                    &self.payer_output_account,
+                    &self.output_vault_account,
+                    amount,
+                )
+            }
+            _ => err!(CallbackErrors::UnknownMode),
+        }
+    }
+
+    fn transfer(
+        &self,
+        mint: &InterfaceAccount<'info, Mint>,
+        from: &InterfaceAccount<'info, TokenAccount>,
+        to: &InterfaceAccount<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: mint.to_account_info(),
+            from: from.to_account_info(),
+            to: to.to_account_info(),
+            authority: self.payer.to_account_info(),
+        };
+        let cpi_context = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_context, amount, mint.decimals)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+//tick and sqrt price math for concentrated liquidity pools
+//prices are sqrt(price) in Q64.64, price is wsol per usdc like TradeDirection a/b
+
+pub use crate::math::Q64;
+use crate::math::{mul_div, mul_div_ceil, mul_shr_128};
+
+//every tick is a 0.01% price step, sqrt price stays inside [2^-32, 2^32]
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+
+//keeps liquidity << 64 inside u128
+pub const MAX_LIQUIDITY: u128 = u64::MAX as u128;
+
+//fee rate in bps of the input amount
+pub const FEE_RATE_DENOMINATOR: u64 = 10_000;
+pub const MAX_FEE_RATE: u16 = 1_000;
+
+//floor(2^128 / sqrt(1.0001)^(2^i)) for every bit of the tick
+const TICK_RATIOS: [u128; 19] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e2139,
+    0xfff2e50f5f656932ef12357cf3c7fdcb,
+    0xffe5caca7e10e4e61c3624eaa0941ccf,
+    0xffcb9843d60f6159c9db58835c926643,
+    0xff973b41fa98c081472e6896dfb254bf,
+    0xff2ea16466c96a3843ec78b326b52860,
+    0xfe5dee046a99a2a811c461f1969c3052,
+    0xfcbe86c7900a88aedcffc83b479aa3a3,
+    0xf987a7253ac413176f2b074cf7815e53,
+    0xf3392b0822b70005940c7a398e4b70f2,
+    0xe7159475a2c29b7443b29c7fa6e889d8,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e4,
+    0x70d869a156d2a1b890bb3df62baf32f6,
+    0x31be135f97d08fd981231505542fcfa5,
+    0x9aa508b5b7a84e1c677de54f3e99bc8,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe97,
+];
+
+//sqrt(1.0001^tick) in Q64.64
+pub fn sqrt_price_at_tick(tick: i32) -> Option<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+
+    //product of the bit ratios is sqrt(1.0001)^-|tick| in Q128
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = u128::MAX;
+    for (bit, tick_ratio) in TICK_RATIOS.iter().enumerate() {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = mul_shr_128(ratio, *tick_ratio);
+        }
+    }
+
+    if tick > 0 {
+        //2^192 / ratio is the inverse in Q64.64
+        mul_div(1 << 127, 1 << 65, ratio)
+    } else {
+        Some(ratio >> 64)
+    }
+}
+
+//largest tick whose sqrt price is at or below sqrt_price
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> Option<i32> {
+    if sqrt_price < sqrt_price_at_tick(MIN_TICK)? || sqrt_price > sqrt_price_at_tick(MAX_TICK)? {
+        return None;
+    }
+
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let middle = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(middle)? <= sqrt_price {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    Some(low)
+}
+
+//usdc needed to move liquidity between two sqrt prices
+//liquidity * (upper - lower) / (upper * lower)
+pub fn amount_a_delta(
+    sqrt_price_a: u128,
+    sqrt_price_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let (lower, upper) = sorted(sqrt_price_a, sqrt_price_b);
+    if lower == 0 || liquidity > MAX_LIQUIDITY {
+        return None;
+    }
+
+    let numerator = liquidity << 64;
+    if round_up {
+        Some(mul_div_ceil(numerator, upper - lower, upper)?.div_ceil(lower))
+    } else {
+        Some(mul_div(numerator, upper - lower, upper)? / lower)
+    }
+}
+
+//wsol needed to move liquidity between two sqrt prices
+//liquidity * (upper - lower)
+pub fn amount_b_delta(
+    sqrt_price_a: u128,
+    sqrt_price_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let (lower, upper) = sorted(sqrt_price_a, sqrt_price_b);
+    if round_up {
+        mul_div_ceil(liquidity, upper - lower, Q64)
+    } else {
+        mul_div(liquidity, upper - lower, Q64)
+    }
+}
+
+fn sorted(a: u128, b: u128) -> (u128, u128) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+//sqrt price after putting amount_in into the pool, rounded against the trader
+//usdc in pushes the price down, wsol in pushes it up
+pub fn next_sqrt_price_from_input(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount_in: u128,
+    a_to_b: bool,
+) -> Option<u128> {
+    if liquidity == 0 || liquidity > MAX_LIQUIDITY {
+        return None;
+    }
+    if amount_in == 0 {
+        return Some(sqrt_price);
+    }
+
+    if a_to_b {
+        //liquidity / (liquidity / sqrt_price + amount_in)
+        let numerator = liquidity << 64;
+        let denominator = (numerator / sqrt_price).checked_add(amount_in)?;
+        Some(numerator.div_ceil(denominator))
+    } else {
+        //sqrt_price + amount_in / liquidity
+        let delta = mul_div(amount_in, Q64, liquidity)?;
+        sqrt_price.checked_add(delta)
+    }
+}
+
+//result of swapping inside one range of constant liquidity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next: u128,
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub fee_amount: u128,
+}
+
+//exact input step towards sqrt_price_target, stops early if the input runs out
+pub fn compute_swap_step(
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u128,
+    fee_rate: u16,
+    a_to_b: bool,
+) -> Option<SwapStep> {
+    let fee_denominator = FEE_RATE_DENOMINATOR as u128;
+    let fee_rate = fee_rate as u128;
+    let amount_less_fee = mul_div(
+        amount_remaining,
+        fee_denominator - fee_rate,
+        fee_denominator,
+    )?;
+
+    let amount_to_target = if a_to_b {
+        amount_a_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?
+    } else {
+        amount_b_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?
+    };
+
+    let reached_target = amount_less_fee >= amount_to_target;
+    let sqrt_price_next = if reached_target {
+        sqrt_price_target
+    } else {
+        next_sqrt_price_from_input(sqrt_price_current, liquidity, amount_less_fee, a_to_b)?
+    };
+
+    let (amount_in, amount_out) = if a_to_b {
+        (
+            amount_a_delta(sqrt_price_next, sqrt_price_current, liquidity, true)?,
+            amount_b_delta(sqrt_price_next, sqrt_price_current, liquidity, false)?,
+        )
+    } else {
+        (
+            amount_b_delta(sqrt_price_current, sqrt_price_next, liquidity, true)?,
+            amount_a_delta(sqrt_price_current, sqrt_price_next, liquidity, false)?,
+        )
+    };
+
+    //whatever is left of the input when the step stops early is fee
+    let fee_amount = if reached_target {
+        mul_div_ceil(amount_in, fee_rate, fee_denominator - fee_rate)?
+    } else {
+        amount_remaining.checked_sub(amount_in)?
+    };
+
+    Some(SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+//usdc and wsol backing liquidity in [tick_lower, tick_upper) at the current price
+pub fn amounts_for_liquidity(
+    sqrt_price: u128,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<(u128, u128)> {
+    let sqrt_price_lower = sqrt_price_at_tick(tick_lower)?;
+    let sqrt_price_upper = sqrt_price_at_tick(tick_upper)?;
+
+    if sqrt_price <= sqrt_price_lower {
+        //range is above the price, only usdc
+        let amount_a = amount_a_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?;
+        Some((amount_a, 0))
+    } else if sqrt_price < sqrt_price_upper {
+        let amount_a = amount_a_delta(sqrt_price, sqrt_price_upper, liquidity, round_up)?;
+        let amount_b = amount_b_delta(sqrt_price_lower, sqrt_price, liquidity, round_up)?;
+        Some((amount_a, amount_b))
+    } else {
+        //range is below the price, only wsol
+        let amount_b = amount_b_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?;
+        Some((0, amount_b))
+    }
+}
+
+//fee growth per unit of liquidity inside a range, all values wrap like uniswap v3
+pub fn fee_growth_inside(
+    current_tick: i32,
+    tick_lower: i32,
+    fee_growth_outside_lower: u128,
+    tick_upper: i32,
+    fee_growth_outside_upper: u128,
+    fee_growth_global: u128,
+) -> u128 {
+    let below = if current_tick >= tick_lower {
+        fee_growth_outside_lower
+    } else {
+        fee_growth_global.wrapping_sub(fee_growth_outside_lower)
+    };
+    let above = if current_tick < tick_upper {
+        fee_growth_outside_upper
+    } else {
+        fee_growth_global.wrapping_sub(fee_growth_outside_upper)
+    };
+    fee_growth_global.wrapping_sub(below).wrapping_sub(above)
+}
+
+//fees earned by liquidity since the last checkpoint
+pub fn fees_earned(liquidity: u128, fee_growth_inside: u128, fee_growth_last: u128) -> Option<u64> {
+    let earned = mul_div(
+        liquidity,
+        fee_growth_inside.wrapping_sub(fee_growth_last),
+        Q64,
+    )?;
+    u64::try_from(earned).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(sqrt_price: u128) -> f64 {
+        let sqrt_price = sqrt_price as f64 / Q64 as f64;
+        sqrt_price * sqrt_price
+    }
+
+    #[test]
+    fn sqrt_price_matches_floats() {
+        assert_eq!(sqrt_price_at_tick(0), Some(Q64 - 1));
+        for tick in [-443_636, -100_000, -1, 1, 64, 100_000, 443_636] {
+            let expected = 1.0001f64.powi(tick);
+            let actual = price(sqrt_price_at_tick(tick).unwrap());
+            assert!(((actual - expected) / expected).abs() < 1e-9, "tick {tick}");
+        }
+        assert_eq!(sqrt_price_at_tick(MAX_TICK + 1), None);
+        assert_eq!(sqrt_price_at_tick(MIN_TICK - 1), None);
+    }
+
+    #[test]
+    fn tick_at_sqrt_price_inverts() {
+        for tick in [-443_636, -5000, -1, 0, 1, 77, 5000, 443_636] {
+            let sqrt_price = sqrt_price_at_tick(tick).unwrap();
+            assert_eq!(tick_at_sqrt_price(sqrt_price), Some(tick));
+            if tick < MAX_TICK {
+                assert_eq!(tick_at_sqrt_price(sqrt_price + 1), Some(tick));
+            }
+        }
+    }
+
+    #[test]
+    fn swap_step_stops_at_target() {
+        let current = sqrt_price_at_tick(0).unwrap();
+        let target = sqrt_price_at_tick(-100).unwrap();
+        let liquidity = 1_000_000_000u128;
+
+        //plenty of input reaches the target
+        let step = compute_swap_step(current, target, liquidity, 1_000_000_000, 30, true).unwrap();
+        assert_eq!(step.sqrt_price_next, target);
+        assert!(step.amount_in + step.fee_amount <= 1_000_000_000);
+        assert!(step.amount_out < step.amount_in);
+
+        //a small input stays inside the range and uses all of it
+        let step = compute_swap_step(current, target, liquidity, 1_000, 30, true).unwrap();
+        assert!(step.sqrt_price_next < current && step.sqrt_price_next > target);
+        assert_eq!(step.amount_in + step.fee_amount, 1_000);
+    }
+
+    #[test]
+    fn round_trip_does_not_create_value() {
+        let liquidity = 5_000_000_000u128;
+        let (deposit_a, deposit_b) =
+            amounts_for_liquidity(sqrt_price_at_tick(0).unwrap(), -600, 600, liquidity, true)
+                .unwrap();
+        let (withdraw_a, withdraw_b) =
+            amounts_for_liquidity(sqrt_price_at_tick(0).unwrap(), -600, 600, liquidity, false)
+                .unwrap();
+        assert!(withdraw_a <= deposit_a && withdraw_b <= deposit_b);
+        assert!(deposit_a > 0 && deposit_b > 0);
+
+        //out of range positions hold one token
+        let (above_a, above_b) = amounts_for_liquidity(
+            sqrt_price_at_tick(-1000).unwrap(),
+            -600,
+            600,
+            liquidity,
+            true,
+        )
+        .unwrap();
+        assert!(above_a > 0 && above_b == 0);
+    }
+
+    #[test]
+    fn fee_growth_inside_wraps() {
+        //price inside the range
+        assert_eq!(fee_growth_inside(0, -10, 3, 10, 4, 20), 13);
+        //price below the range, growth above the lower tick minus above the upper one
+        assert_eq!(fee_growth_inside(-20, -10, 15, 10, 4, 20), 11);
+        //wrapped checkpoints still give the difference
+        let inside = fee_growth_inside(0, -10, 0, 10, 0, 5);
+        assert_eq!(fees_earned(Q64, inside, u128::MAX - 1), Some(7));
+    }
+}
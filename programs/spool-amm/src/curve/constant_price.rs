@@ -0,0 +1,73 @@
+//fixed price between the tokens, value = a + b * price
+
+use super::{Curve, TradeDirection};
+use crate::math::mul_div;
+
+pub struct ConstantPriceCurve {
+    //price of one token b unit in token a units
+    pub token_b_price: u64,
+}
+
+impl ConstantPriceCurve {
+    fn value(&self, amount_a: u64, amount_b: u64) -> Option<u128> {
+        (amount_b as u128)
+            .checked_mul(self.token_b_price as u128)?
+            .checked_add(amount_a as u128)
+    }
+}
+
+impl Curve for ConstantPriceCurve {
+    fn swap(
+        &self,
+        amount_in: u64,
+        _reserve_in: u64,
+        reserve_out: u64,
+        direction: TradeDirection,
+    ) -> Option<u64> {
+        let output = match direction {
+            TradeDirection::AtoB => amount_in as u128 / self.token_b_price as u128,
+            TradeDirection::BtoA => (amount_in as u128).checked_mul(self.token_b_price as u128)?,
+        };
+        if output > reserve_out as u128 {
+            return None;
+        }
+        Some(output as u64)
+    }
+
+    fn deposit(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Option<u64> {
+        let deposit_value = self.value(amount_a, amount_b)?;
+        if supply == 0 {
+            return u64::try_from(deposit_value).ok();
+        }
+
+        let pool_value = self.value(reserve_a, reserve_b)?;
+        u64::try_from(mul_div(deposit_value, supply as u128, pool_value)?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_at_the_fixed_price() {
+        let curve = ConstantPriceCurve { token_b_price: 4 };
+        assert_eq!(curve.swap(100, 0, 1_000, TradeDirection::AtoB), Some(25));
+        assert_eq!(curve.swap(25, 0, 1_000, TradeDirection::BtoA), Some(100));
+        assert_eq!(curve.swap(300, 0, 1_000, TradeDirection::BtoA), None);
+    }
+
+    #[test]
+    fn deposit_by_value() {
+        let curve = ConstantPriceCurve { token_b_price: 4 };
+        assert_eq!(curve.deposit(100, 25, 0, 0, 0), Some(200));
+        assert_eq!(curve.deposit(200, 0, 100, 25, 200), Some(200));
+    }
+}
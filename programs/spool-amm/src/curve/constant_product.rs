@@ -0,0 +1,179 @@
+//x * y = k
+
+use super::{Curve, TradeDirection, FEE_DENOMINATOR};
+use crate::math::{mul_div, mul_div_ceil, sqrt_of_product};
+
+pub struct ConstantProductCurve;
+
+//output of x * y = k, the product must not drop
+pub fn swap_output(amount_in: u64, reserve_in: u64, reserve_out: u64) -> Option<u64> {
+    let reserve_in = reserve_in as u128;
+    let reserve_out = reserve_out as u128;
+    let amount_in = amount_in as u128;
+
+    let output = mul_div(reserve_out, amount_in, reserve_in.checked_add(amount_in)?)?;
+
+    let product_before = reserve_in.checked_mul(reserve_out)?;
+    let product_after = (reserve_in + amount_in).checked_mul(reserve_out - output)?;
+    if product_after < product_before {
+        return None;
+    }
+    u64::try_from(output).ok()
+}
+
+//part of a single sided deposit to swap so both sides match the pool ratio after the swap
+//s = (sqrt(r * (r * (2 - f)^2 + 4 * (1 - f) * a)) - r * (2 - f)) / (2 * (1 - f)), taken over
+//the fee denominator and rationalized to s = 2 * a * r / (sqrt(...) + r * (2 - f))
+pub fn zap_swap_amount(reserve_in: u64, amount: u64, fee_bps: u16) -> Option<u64> {
+    let (reserve, amount_in, fee) = (reserve_in as u128, amount as u128, fee_bps as u128);
+    let two_minus_fee = 2 * FEE_DENOMINATOR - fee;
+    let discriminant = reserve
+        .checked_mul(two_minus_fee * two_minus_fee)?
+        .checked_add(4 * (FEE_DENOMINATOR - fee) * FEE_DENOMINATOR * amount_in)?;
+    let root = sqrt_of_product(reserve, discriminant)?;
+
+    //rounded up, the swap output and the fee round against the depositor
+    let swap_amount = mul_div_ceil(
+        2 * FEE_DENOMINATOR * amount_in,
+        reserve,
+        root.checked_add(reserve * two_minus_fee)?,
+    )?;
+    Some(swap_amount.min(amount_in) as u64)
+}
+
+//smaller of the two shares, anything above it stays with the pool
+pub fn proportional_lp(amount: u64, reserve: u64, supply: u64) -> Option<u128> {
+    mul_div(amount as u128, supply as u128, reserve as u128)
+}
+
+//protocol share of the sqrt(k) growth, 1/6 like uniswap v2
+pub const PROTOCOL_FEE_SHARE: u128 = 6;
+
+//lp to mint to the protocol for the sqrt(k) growth since k_last
+//supply * (root_k - root_k_last) / (root_k * (share - 1) + root_k_last)
+pub fn protocol_fee_lp(reserve_a: u64, reserve_b: u64, k_last: u128, supply: u64) -> Option<u64> {
+    if k_last == 0 {
+        return Some(0);
+    }
+
+    let root_k = (reserve_a as u128).checked_mul(reserve_b as u128)?.isqrt();
+    let root_k_last = k_last.isqrt();
+    if root_k <= root_k_last {
+        return Some(0);
+    }
+
+    let denominator = root_k
+        .checked_mul(PROTOCOL_FEE_SHARE - 1)?
+        .checked_add(root_k_last)?;
+    let liquidity = mul_div(supply as u128, root_k - root_k_last, denominator)?;
+    u64::try_from(liquidity).ok()
+}
+
+impl Curve for ConstantProductCurve {
+    fn swap(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        _direction: TradeDirection,
+    ) -> Option<u64> {
+        swap_output(amount_in, reserve_in, reserve_out)
+    }
+
+    fn deposit(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Option<u64> {
+        if supply == 0 {
+            let product = (amount_a as u128).checked_mul(amount_b as u128)?;
+            return u64::try_from(product.isqrt()).ok();
+        }
+
+        let share_a = proportional_lp(amount_a, reserve_a, supply)?;
+        let share_b = proportional_lp(amount_b, reserve_b, supply)?;
+        u64::try_from(share_a.min(share_b)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_keeps_the_product() {
+        let output = swap_output(1_000, 1_000_000, 2_000_000).unwrap();
+        assert_eq!(output, 1_998);
+        assert!(1_001_000u128 * (2_000_000 - output) as u128 >= 1_000_000u128 * 2_000_000);
+    }
+
+    #[test]
+    fn first_deposit_is_geometric_mean() {
+        let curve = ConstantProductCurve;
+        assert_eq!(curve.deposit(100, 400, 0, 0, 0), Some(200));
+    }
+
+    #[test]
+    fn deposit_takes_the_smaller_share() {
+        let curve = ConstantProductCurve;
+        let lp = curve.deposit(100, 1_000, 1_000, 2_000, 500).unwrap();
+        assert_eq!(lp, 50);
+    }
+
+    #[test]
+    fn protocol_fee_is_a_sixth_of_the_growth() {
+        //k grew from 1_000_000^2 to 1_210_000^2 with 1_000_000 lp outstanding
+        let fee =
+            protocol_fee_lp(1_210_000, 1_210_000, 1_000_000u128 * 1_000_000, 1_000_000).unwrap();
+        assert_eq!(fee, 29_787);
+
+        //the fee lp is worth 1/6 of the growth in sqrt(k), rounded down
+        let value = 1_210_000u128 * fee as u128 / (1_000_000 + fee as u128);
+        assert_eq!(value, 34_999);
+
+        assert_eq!(protocol_fee_lp(1_000, 1_000, 0, 1_000), Some(0));
+        assert_eq!(protocol_fee_lp(1_000, 1_000, 2_000_000, 1_000), Some(0));
+    }
+
+    #[test]
+    fn zap_leaves_the_pool_ratio() {
+        for (reserve_in, reserve_out, amount, fee_bps) in [
+            (1_000_000_000u64, 5_000_000_000u64, 100_000_000u64, 30u16),
+            (100_000_000, 1_000_000_000, 3_000_000, 100),
+            (u64::MAX / 4, u64::MAX / 8, 1 << 60, 5),
+            (1_000_000, 3_000_000, 10_000_000, 1),
+            (5_000_000_000, 1_000_000_000, 100_000_000, 30),
+        ] {
+            let swap_amount = zap_swap_amount(reserve_in, amount, fee_bps).unwrap();
+            let swap_in = super::super::amount_after_fee(swap_amount, fee_bps);
+            let swap_out = swap_output(swap_in, reserve_in, reserve_out).unwrap() as u128;
+
+            //what is left to deposit is at the ratio of the reserves after the swap, to one
+            //unit of the side with the larger unit value
+            let left = (amount - swap_amount) as u128;
+            let reserve_in = (reserve_in + swap_amount) as u128;
+            let reserve_out = reserve_out as u128 - swap_out;
+            let difference = if reserve_in >= reserve_out {
+                mul_div(left, reserve_out, reserve_in)
+                    .unwrap()
+                    .abs_diff(swap_out)
+            } else {
+                mul_div(swap_out, reserve_in, reserve_out)
+                    .unwrap()
+                    .abs_diff(left)
+            };
+            assert!(difference <= 1, "off by {difference}");
+        }
+        assert_eq!(zap_swap_amount(0, 1_000, 30), None);
+    }
+
+    #[test]
+    fn withdraw_is_pro_rata() {
+        let curve = ConstantProductCurve;
+        assert_eq!(curve.withdraw(50, 1_000, 2_000, 500), Some((100, 200)));
+        assert_eq!(curve.withdraw(1, 1_000, 2_000, 0), None);
+    }
+}
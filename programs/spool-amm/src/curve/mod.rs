@@ -0,0 +1,155 @@
+//pricing curves, pure math without any accounts
+//swap amounts passed to a curve already had the trading fee taken
+
+use anchor_lang::prelude::*;
+
+use crate::math::mul_div;
+
+pub mod constant_price;
+pub mod constant_product;
+pub mod offset;
+pub mod stable;
+pub mod weighted;
+
+//trading fee is the pool fee tier in basis points, also charged by the stable curve on
+//imbalanced deposits
+pub const FEE_DENOMINATOR: u128 = 10_000;
+
+//input left for the curve once the trading fee is taken
+pub fn amount_after_fee(amount_in: u64, fee_bps: u16) -> u64 {
+    let amount = amount_in as u128;
+    (amount - (amount * fee_bps as u128) / FEE_DENOMINATOR) as u64
+}
+
+//largest part of (amount_a, amount_b) at the reserve ratio, an empty pool takes both amounts
+pub fn amounts_at_ratio(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Option<(u64, u64)> {
+    if reserve_a == 0 && reserve_b == 0 {
+        return Some((amount_a, amount_b));
+    }
+    if reserve_a == 0 {
+        return Some((0, amount_b));
+    }
+
+    let b_for_a = mul_div(amount_a as u128, reserve_b as u128, reserve_a as u128)?;
+    if b_for_a <= amount_b as u128 {
+        return Some((amount_a, b_for_a as u64));
+    }
+    let a_for_b = mul_div(amount_b as u128, reserve_a as u128, reserve_b as u128)?;
+    Some((a_for_b.min(amount_a as u128) as u64, amount_b))
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum CurveType {
+    //x * y = k
+    ConstantProduct,
+    //stableswap, parameter is the amplification coefficient
+    Stable,
+    //fixed price, parameter is the price of token b in token a
+    ConstantPrice,
+    //x * (y + offset) = k, parameter is the token b offset
+    Offset,
+    //weighted product, parameter is the token a weight in basis points
+    Weighted,
+}
+
+//a is the usdc side of the pool, b the wsol side
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TradeDirection {
+    AtoB,
+    BtoA,
+}
+
+pub trait Curve {
+    //output for amount_in, reserves are in the direction of the trade
+    fn swap(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        direction: TradeDirection,
+    ) -> Option<u64>;
+
+    //lp for a deposit, with zero supply this is the initial liquidity
+    fn deposit(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Option<u64>;
+
+    //pro rata amounts for burning lp
+    fn withdraw(
+        &self,
+        burn_amount: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Option<(u64, u64)> {
+        if supply == 0 || burn_amount > supply {
+            return None;
+        }
+        let amount_a = mul_div(burn_amount as u128, reserve_a as u128, supply as u128)?;
+        let amount_b = mul_div(burn_amount as u128, reserve_b as u128, supply as u128)?;
+        Some((amount_a as u64, amount_b as u64))
+    }
+}
+
+impl CurveType {
+    //parameter bounds checked at initialize
+    pub fn is_valid_parameter(&self, parameter: u64) -> bool {
+        match self {
+            CurveType::ConstantProduct => parameter == 0,
+            CurveType::Stable => (stable::MIN_AMP..=stable::MAX_AMP).contains(&parameter),
+            CurveType::ConstantPrice => parameter != 0,
+            CurveType::Offset => parameter != 0,
+            CurveType::Weighted => {
+                (weighted::MIN_WEIGHT..=weighted::MAX_WEIGHT).contains(&parameter)
+            }
+        }
+    }
+}
+
+pub fn build(curve_type: CurveType, parameter: u64, fee_bps: u16) -> Box<dyn Curve> {
+    match curve_type {
+        CurveType::ConstantProduct => Box::new(constant_product::ConstantProductCurve),
+        CurveType::Stable => Box::new(stable::StableCurve {
+            amp: parameter,
+            fee: (fee_bps as u128, FEE_DENOMINATOR),
+        }),
+        CurveType::ConstantPrice => Box::new(constant_price::ConstantPriceCurve {
+            token_b_price: parameter,
+        }),
+        CurveType::Offset => Box::new(offset::OffsetCurve {
+            token_b_offset: parameter,
+        }),
+        CurveType::Weighted => Box::new(weighted::WeightedCurve {
+            token_a_weight: parameter,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio_keeps_the_smaller_side() {
+        assert_eq!(amounts_at_ratio(100, 1_000, 10, 50), Some((100, 500)));
+        assert_eq!(amounts_at_ratio(100, 200, 10, 50), Some((40, 200)));
+        assert_eq!(amounts_at_ratio(100, 500, 10, 50), Some((100, 500)));
+    }
+
+    #[test]
+    fn ratio_with_empty_reserves() {
+        assert_eq!(amounts_at_ratio(100, 200, 0, 0), Some((100, 200)));
+        assert_eq!(amounts_at_ratio(100, 200, 10, 0), Some((100, 0)));
+        assert_eq!(amounts_at_ratio(100, 200, 0, 10), Some((0, 200)));
+    }
+}
@@ -0,0 +1,85 @@
+//x * (y + offset) = k, lets a pool start with token a only
+
+use super::{constant_product, Curve, TradeDirection};
+
+pub struct OffsetCurve {
+    //virtual amount added to the token b reserve
+    pub token_b_offset: u64,
+}
+
+impl Curve for OffsetCurve {
+    fn swap(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        direction: TradeDirection,
+    ) -> Option<u64> {
+        let output = match direction {
+            TradeDirection::AtoB => {
+                let virtual_out = reserve_out.checked_add(self.token_b_offset)?;
+                constant_product::swap_output(amount_in, reserve_in, virtual_out)?
+            }
+            TradeDirection::BtoA => {
+                let virtual_in = reserve_in.checked_add(self.token_b_offset)?;
+                constant_product::swap_output(amount_in, virtual_in, reserve_out)?
+            }
+        };
+
+        //only real tokens can leave the pool
+        if output > reserve_out {
+            return None;
+        }
+        Some(output)
+    }
+
+    fn deposit(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Option<u64> {
+        if supply == 0 {
+            let virtual_b = amount_b.checked_add(self.token_b_offset)?;
+            let product = (amount_a as u128).checked_mul(virtual_b as u128)?;
+            return u64::try_from(product.isqrt()).ok();
+        }
+
+        //no token b in the pool yet, token a alone sets the share
+        let share_a = constant_product::proportional_lp(amount_a, reserve_a, supply)?;
+        if reserve_b == 0 {
+            return u64::try_from(share_a).ok();
+        }
+        let share_b = constant_product::proportional_lp(amount_b, reserve_b, supply)?;
+        u64::try_from(share_a.min(share_b)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_against_the_offset() {
+        let curve = OffsetCurve {
+            token_b_offset: 1_000_000,
+        };
+        //no real token b yet, only b to a trades can fill
+        assert_eq!(curve.swap(1_000, 1_000_000, 0, TradeDirection::AtoB), None);
+        let output = curve
+            .swap(1_000, 0, 1_000_000, TradeDirection::BtoA)
+            .unwrap();
+        assert_eq!(output, 999);
+    }
+
+    #[test]
+    fn first_deposit_with_token_a_only() {
+        let curve = OffsetCurve {
+            token_b_offset: 400,
+        };
+        assert_eq!(curve.deposit(100, 0, 0, 0, 0), Some(200));
+        assert_eq!(curve.deposit(50, 0, 100, 0, 200), Some(100));
+    }
+}
@@ -0,0 +1,208 @@
+//curve style stableswap invariant for two coins
+//A * n^n * sum(x) + D = A * D * n^n + D^(n + 1) / (n^n * prod(x))
+
+use super::{Curve, TradeDirection};
+use crate::math::{mul_div, mul_div_ceil};
+
+const N_COINS: u128 = 2;
+
+//newton iterations before giving up
+const MAX_ITERATIONS: usize = 255;
+
+//bounds for the amplification coefficient, zero is rejected, pick the constant product curve instead
+pub const MIN_AMP: u64 = 1;
+pub const MAX_AMP: u64 = 1_000_000;
+
+//invariant D for the two reserves
+pub fn compute_d(amp: u64, reserve_a: u128, reserve_b: u128) -> Option<u128> {
+    let sum = reserve_a.checked_add(reserve_b)?;
+    if sum == 0 {
+        return Some(0);
+    }
+    if reserve_a == 0 || reserve_b == 0 {
+        return None;
+    }
+
+    let ann = (amp as u128).checked_mul(N_COINS)?;
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        //d_p = D^(n + 1) / (n^n * prod(x))
+        let mut d_p = d;
+        d_p = mul_div(d_p, d, reserve_a.checked_mul(N_COINS)?)?;
+        d_p = mul_div(d_p, d, reserve_b.checked_mul(N_COINS)?)?;
+
+        let d_prev = d;
+        let numerator = ann
+            .checked_mul(sum)?
+            .checked_add(d_p.checked_mul(N_COINS)?)?;
+        let denominator = (ann - 1)
+            .checked_mul(d)?
+            .checked_add((N_COINS + 1).checked_mul(d_p)?)?;
+        d = mul_div(numerator, d, denominator)?;
+
+        if d.abs_diff(d_prev) <= 1 {
+            return Some(d);
+        }
+    }
+    None
+}
+
+//other reserve for a new reserve_in while keeping D
+pub fn compute_y(amp: u64, reserve_in: u128, d: u128) -> Option<u128> {
+    let ann = (amp as u128).checked_mul(N_COINS)?;
+
+    //c = D^(n + 1) / (n^n * x * ann), b = x + D / ann
+    let mut c = d;
+    c = mul_div(c, d, reserve_in.checked_mul(N_COINS)?)?;
+    c = mul_div(c, d, ann.checked_mul(N_COINS)?)?;
+    let b = reserve_in.checked_add(d / ann)?;
+
+    //y = (y^2 + c) / (2y + b - D), rounded up so the pool keeps the rounding
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        let denominator = y.checked_mul(2)?.checked_add(b)?.checked_sub(d)?;
+        y = mul_div_ceil(y, y, denominator)?.checked_add(c.div_ceil(denominator))?;
+
+        if y.abs_diff(y_prev) <= 1 {
+            return Some(y);
+        }
+    }
+    None
+}
+
+//output for an input that already had the fee taken, rounded in favour of the pool
+pub fn swap_output(amp: u64, reserve_in: u64, reserve_out: u64, amount_in: u64) -> Option<u64> {
+    let d = compute_d(amp, reserve_in as u128, reserve_out as u128)?;
+    let new_reserve_in = (reserve_in as u128).checked_add(amount_in as u128)?;
+    let new_reserve_out = compute_y(amp, new_reserve_in, d)?;
+
+    let output = (reserve_out as u128)
+        .checked_sub(new_reserve_out)?
+        .saturating_sub(1);
+    u64::try_from(output).ok()
+}
+
+//lp for a deposit, imbalanced deposits pay the swap fee on the imbalanced part
+//returns D of the new reserves when the pool is empty
+pub fn deposit_lp(
+    amp: u64,
+    reserves: (u64, u64),
+    amounts: (u64, u64),
+    supply: u64,
+    fee: (u128, u128),
+) -> Option<u64> {
+    let (reserve_a, reserve_b) = (reserves.0 as u128, reserves.1 as u128);
+    let new_a = reserve_a.checked_add(amounts.0 as u128)?;
+    let new_b = reserve_b.checked_add(amounts.1 as u128)?;
+
+    let d1 = compute_d(amp, new_a, new_b)?;
+    if supply == 0 {
+        return u64::try_from(d1).ok();
+    }
+
+    let d0 = compute_d(amp, reserve_a, reserve_b)?;
+    if d1 <= d0 {
+        return Some(0);
+    }
+
+    //fee on the distance from the balanced deposit, n / (4 * (n - 1)) of the swap fee
+    let (fee_numerator, fee_denominator) = fee;
+    let imbalance_fee = |reserve: u128, new_reserve: u128| -> Option<u128> {
+        let ideal = mul_div(d1, reserve, d0)?;
+        let difference = ideal.abs_diff(new_reserve);
+        mul_div(
+            difference,
+            fee_numerator * N_COINS,
+            fee_denominator * 4 * (N_COINS - 1),
+        )
+    };
+    let adjusted_a = new_a.checked_sub(imbalance_fee(reserve_a, new_a)?)?;
+    let adjusted_b = new_b.checked_sub(imbalance_fee(reserve_b, new_b)?)?;
+
+    let d2 = compute_d(amp, adjusted_a, adjusted_b)?;
+    let lp = mul_div(supply as u128, d2.checked_sub(d0)?, d0)?;
+    u64::try_from(lp).ok()
+}
+
+pub struct StableCurve {
+    pub amp: u64,
+    //swap fee, charged on imbalanced deposits
+    pub fee: (u128, u128),
+}
+
+impl Curve for StableCurve {
+    fn swap(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        _direction: TradeDirection,
+    ) -> Option<u64> {
+        swap_output(self.amp, reserve_in, reserve_out, amount_in)
+    }
+
+    fn deposit(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Option<u64> {
+        deposit_lp(
+            self.amp,
+            (reserve_a, reserve_b),
+            (amount_a, amount_b),
+            supply,
+            self.fee,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::FEE_DENOMINATOR;
+
+    const FEE: (u128, u128) = (30, FEE_DENOMINATOR);
+
+    #[test]
+    fn d_of_balanced_pool_is_the_sum() {
+        let d = compute_d(100, 1_000_000_000, 1_000_000_000).unwrap();
+        assert!(d.abs_diff(2_000_000_000) <= 1);
+    }
+
+    #[test]
+    fn y_recovers_the_reserve() {
+        let d = compute_d(100, 5_000_000, 7_000_000).unwrap();
+        let y = compute_y(100, 5_000_000, d).unwrap();
+        assert!(y.abs_diff(7_000_000) <= 2);
+    }
+
+    #[test]
+    fn swap_close_to_one_for_balanced_pool() {
+        let reserve = 1_000_000_000_000;
+        let output = swap_output(200, reserve, reserve, 1_000_000).unwrap();
+        assert!(output < 1_000_000);
+        assert!(output > 999_000);
+    }
+
+    #[test]
+    fn swap_handles_large_reserves() {
+        let reserve = u64::MAX / 4;
+        let output = swap_output(MAX_AMP, reserve, reserve, 1_000_000_000).unwrap();
+        assert!(output < 1_000_000_000);
+        assert!(output > 999_000_000);
+    }
+
+    #[test]
+    fn imbalanced_deposit_mints_less() {
+        let reserves = (1_000_000_000, 1_000_000_000);
+        let supply = 2_000_000_000;
+        let balanced = deposit_lp(100, reserves, (1_000_000, 1_000_000), supply, FEE).unwrap();
+        let single = deposit_lp(100, reserves, (2_000_000, 0), supply, FEE).unwrap();
+        assert!(balanced.abs_diff(2_000_000) <= 2);
+        assert!(single < balanced);
+    }
+}
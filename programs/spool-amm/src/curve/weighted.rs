@@ -0,0 +1,161 @@
+//balancer style weighted product, prod(balance ^ weight) = k
+
+use super::{constant_product, Curve, TradeDirection};
+use crate::math::{exp, ln, mul_div, mul_div_ceil, pow, MAX_POW_RELATIVE_ERROR, ONE};
+
+//weights are in basis points of the whole pool
+pub const WEIGHT_DENOMINATOR: u64 = 10_000;
+pub const MIN_WEIGHT: u64 = 100;
+pub const MAX_WEIGHT: u64 = WEIGHT_DENOMINATOR - MIN_WEIGHT;
+
+//largest trade as a share of the input reserve, keeps pow inside its error bound
+const MAX_IN_RATIO_NUMERATOR: u128 = 3;
+const MAX_IN_RATIO_DENOMINATOR: u128 = 10;
+
+pub struct WeightedCurve {
+    //weight of token a, token b gets the rest
+    pub token_a_weight: u64,
+}
+
+impl WeightedCurve {
+    fn weights(&self, direction: TradeDirection) -> (u64, u64) {
+        let weight_a = self.token_a_weight;
+        let weight_b = WEIGHT_DENOMINATOR - weight_a;
+        match direction {
+            TradeDirection::AtoB => (weight_a, weight_b),
+            TradeDirection::BtoA => (weight_b, weight_a),
+        }
+    }
+
+    //invariant of the reserves, in token units
+    fn invariant(&self, reserve_a: u64, reserve_b: u64) -> Option<u128> {
+        let ln_a = ln((reserve_a as u128).checked_mul(ONE)?)?;
+        let ln_b = ln((reserve_b as u128).checked_mul(ONE)?)?;
+        let weight_a = self.token_a_weight as i128;
+        let weight_b = (WEIGHT_DENOMINATOR - self.token_a_weight) as i128;
+
+        let exponent = (ln_a.checked_mul(weight_a)? + ln_b.checked_mul(weight_b)?)
+            / WEIGHT_DENOMINATOR as i128;
+        Some(exp(exponent)? / ONE)
+    }
+}
+
+impl Curve for WeightedCurve {
+    //out = reserve_out * (1 - (reserve_in / (reserve_in + in)) ^ (weight_in / weight_out))
+    fn swap(
+        &self,
+        amount_in: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        direction: TradeDirection,
+    ) -> Option<u64> {
+        let (weight_in, weight_out) = self.weights(direction);
+        let reserve_in = reserve_in as u128;
+        let amount_in = amount_in as u128;
+        if amount_in * MAX_IN_RATIO_DENOMINATOR > reserve_in * MAX_IN_RATIO_NUMERATOR {
+            return None;
+        }
+
+        //everything rounds in favour of the pool
+        let base = mul_div_ceil(reserve_in, ONE, reserve_in.checked_add(amount_in)?)?;
+        let exponent = mul_div(weight_in as u128, ONE, weight_out as u128)?;
+        let power = pow(base, exponent)?;
+        let power = power + mul_div_ceil(power, MAX_POW_RELATIVE_ERROR, ONE)?;
+        if power >= ONE {
+            return Some(0);
+        }
+
+        let output = mul_div(reserve_out as u128, ONE - power, ONE)?;
+        u64::try_from(output).ok()
+    }
+
+    //first deposit mints the invariant, later ones the smaller proportional share
+    fn deposit(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        supply: u64,
+    ) -> Option<u64> {
+        if supply == 0 {
+            return u64::try_from(self.invariant(amount_a, amount_b)?).ok();
+        }
+
+        let share_a = constant_product::proportional_lp(amount_a, reserve_a, supply)?;
+        let share_b = constant_product::proportional_lp(amount_b, reserve_b, supply)?;
+        u64::try_from(share_a.min(share_b)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float_swap(
+        weight_in: f64,
+        weight_out: f64,
+        amount_in: f64,
+        reserve_in: f64,
+        reserve_out: f64,
+    ) -> f64 {
+        reserve_out * (1.0 - (reserve_in / (reserve_in + amount_in)).powf(weight_in / weight_out))
+    }
+
+    #[test]
+    fn even_weights_match_constant_product() {
+        let curve = WeightedCurve {
+            token_a_weight: 5_000,
+        };
+        let weighted = curve
+            .swap(
+                1_000_000,
+                1_000_000_000,
+                2_000_000_000,
+                TradeDirection::AtoB,
+            )
+            .unwrap();
+        let product =
+            constant_product::swap_output(1_000_000, 1_000_000_000, 2_000_000_000).unwrap();
+        assert!(weighted <= product);
+        assert!(product - weighted <= 1);
+    }
+
+    #[test]
+    fn eighty_twenty_swap_matches_floats() {
+        let curve = WeightedCurve {
+            token_a_weight: 8_000,
+        };
+        let output = curve
+            .swap(10_000_000, 800_000_000, 200_000_000, TradeDirection::AtoB)
+            .unwrap();
+        let expected = float_swap(0.8, 0.2, 1e7, 8e8, 2e8);
+        assert!(output as f64 <= expected);
+        assert!(expected - (output as f64) < 2.0);
+
+        let output = curve
+            .swap(10_000_000, 200_000_000, 800_000_000, TradeDirection::BtoA)
+            .unwrap();
+        let expected = float_swap(0.2, 0.8, 1e7, 2e8, 8e8);
+        assert!(output as f64 <= expected);
+        assert!(expected - (output as f64) < 2.0);
+    }
+
+    #[test]
+    fn swap_above_the_max_ratio_fails() {
+        let curve = WeightedCurve {
+            token_a_weight: 8_000,
+        };
+        assert_eq!(curve.swap(400, 1_000, 1_000, TradeDirection::AtoB), None);
+    }
+
+    #[test]
+    fn first_deposit_mints_the_invariant() {
+        let curve = WeightedCurve {
+            token_a_weight: 8_000,
+        };
+        let lp = curve.deposit(1_600_000_000, 100_000_000, 0, 0, 0).unwrap();
+        let expected = 1.6e9f64.powf(0.8) * 1e8f64.powf(0.2);
+        assert!((lp as f64 - expected).abs() < 2.0);
+    }
+}
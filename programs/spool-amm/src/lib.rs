@@ -1,9 +1,27 @@
 use anchor_lang::prelude::*;
+use anchor_lang::{
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        program::invoke,
+    },
+    system_program, Discriminator,
+};
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{self, Token},
-    token_interface::{self, Burn, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked},
+    token::spl_token::native_mint,
+    token_interface::{
+        self, Burn, CloseAccount, FreezeAccount, Mint, MintTo, SyncNative, ThawAccount,
+        TokenAccount, TokenInterface, TransferChecked,
+    },
 };
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
+
+pub mod clmm;
+pub mod curve;
+pub mod math;
+pub mod migration;
+
+use curve::{Curve, CurveType, TradeDirection};
 
 declare_id!("EFuEiBtmr5tPy3iYnQVhMPRVW64R5E1GonrCit8hXa66");
 
@@ -11,25 +29,124 @@ declare_id!("EFuEiBtmr5tPy3iYnQVhMPRVW64R5E1GonrCit8hXa66");
 pub mod spool_amm {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+    //singleton settings shared by every pool, only the upgrade authority can create it
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        params: AmmConfigParams,
+    ) -> Result<()> {
+        ctx.accounts.init_main(ctx.bumps.amm_config, params)?;
+        msg!("amm config created");
+        Ok(())
+    }
+
+    pub fn update_config(ctx: Context<UpdateConfig>, params: AmmConfigParams) -> Result<()> {
+        ctx.accounts.amm_config.apply(params)?;
+        msg!("amm config updated");
+        Ok(())
+    }
+
+    //curve_parameter depends on the curve, see CurveType
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        curve_type: CurveType,
+        curve_parameter: u64,
+        fee_mode: FeeMode,
+        fee_tier_bps: u16,
+    ) -> Result<()> {
         //populate the pool_state_account
-        let pool = &mut ctx.accounts.pool_stateaccount;
-        pool.bump = ctx.bumps.pool_stateaccount;
-        pool.usdc_mint = ctx.accounts.usdc_mint.key();
-        pool.wsol_mint = ctx.accounts.wsol_mint.key();
-        pool.usdc_vault_address = ctx.accounts.usdc_vault.key();
-        pool.wsol_vault_address = ctx.accounts.wsol_vault.key();
-        pool.lp_token_mint = ctx.accounts.mint.key();
+        let accounts = &mut *ctx.accounts;
+        accounts.pool_stateaccount.setup(
+            ctx.bumps.pool_stateaccount,
+            [
+                accounts.usdc_mint.key(),
+                accounts.wsol_mint.key(),
+                accounts.usdc_vault.key(),
+                accounts.wsol_vault.key(),
+                accounts.mint.key(),
+            ],
+            accounts.signer.key(),
+            (curve_type, curve_parameter, fee_tier_bps),
+            fee_mode,
+        )?;
+
+        //creation fee and the registry entry
+        register_new_pool(
+            &mut ctx.accounts.amm_config,
+            &mut ctx.accounts.registry_page,
+            ctx.bumps.registry_page,
+            ctx.accounts.pool_stateaccount.key(),
+            1 << curve_type as u8,
+            fee_tier_bps,
+            &ctx.accounts.payer,
+            &ctx.accounts.fee_receiver,
+            &ctx.accounts.system_program,
+        )?;
         msg!("Greetings from: {:?}", ctx.program_id);
         Ok(())
     }
 
+    //create the pool and make the first deposit in one step, the creator sets the price
+    //claimable pools need the fee checkpoint before the first deposit and use initialize
+    pub fn initialize_with_liquidity(
+        ctx: Context<InitializeWithLiquidity>,
+        curve_type: CurveType,
+        curve_parameter: u64,
+        fee_tier_bps: u16,
+        usdc_amount: u64,
+        wsol_amount: u64,
+    ) -> Result<()> {
+        //same pool setup as initialize, the fees compound
+        let accounts = &mut *ctx.accounts;
+        accounts.pool_stateaccount.setup(
+            ctx.bumps.pool_stateaccount,
+            [
+                accounts.usdc_mint.key(),
+                accounts.wsol_mint.key(),
+                accounts.usdc_vault.key(),
+                accounts.wsol_vault.key(),
+                accounts.mint.key(),
+            ],
+            accounts.signer.key(),
+            (curve_type, curve_parameter, fee_tier_bps),
+            FeeMode::Compound,
+        )?;
+
+        register_new_pool(
+            &mut ctx.accounts.amm_config,
+            &mut ctx.accounts.registry_page,
+            ctx.bumps.registry_page,
+            ctx.accounts.pool_stateaccount.key(),
+            1 << curve_type as u8,
+            fee_tier_bps,
+            &ctx.accounts.payer,
+            &ctx.accounts.fee_receiver,
+            &ctx.accounts.system_program,
+        )?;
+
+        ctx.accounts.init_main(usdc_amount, wsol_amount)?;
+        msg!("pool created with liquidity");
+        Ok(())
+    }
+
     pub fn providelp(ctx: Context<ProvideLp>, wsol_amount: u64, usdc_amount: u64) -> Result<()> {
         ctx.accounts.token_transfer(wsol_amount, usdc_amount)?;
         msg!("providing liquidty is successfull");
         Ok(())
     }
 
+    //deposit only one token, the optimal part is swapped inside the pool
+    pub fn provide_single_sided(
+        ctx: Context<ProvideLp>,
+        token: Pubkey,
+        amount: u64,
+        min_lp_out: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .single_sided_transfer(token, amount, min_lp_out)?;
+        msg!("single sided liquidty is successfull");
+        Ok(())
+    }
+
     //funtion to swap tokens
     pub fn swap(ctx: Context<SwapTokens>, amount_toswap: u64) -> Result<()> {
         ctx.accounts.main_swap_function(amount_toswap)?;
@@ -37,6 +154,19 @@ pub mod spool_amm {
         Ok(())
     }
 
+    //swap through several pools, hops are passed in remaining accounts
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        let remaining_accounts = ctx.remaining_accounts;
+        ctx.accounts
+            .route_swap_main(amount_in, min_amount_out, remaining_accounts)?;
+        msg!("route swap is working");
+        Ok(())
+    }
+
     //function to remove lp
     pub fn remove_liquidity(ctx: Context<RemoveLiquidity>, burnamount: u64) -> Result<()> {
         //call the main function
@@ -44,6 +174,235 @@ pub mod spool_amm {
         msg!("liquidty removed");
         Ok(())
     }
+
+    //burn lp and take everything out in one token
+    pub fn remove_liquidity_single(
+        ctx: Context<RemoveLiquidity>,
+        burnamount: u64,
+        output_token: Pubkey,
+        min_out: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .remove_lp_single(burnamount, output_token, min_out)?;
+        msg!("liquidty removed in a single token");
+        Ok(())
+    }
+
+    //native sol variants, lamports are wrapped into the wsol vault directly
+    pub fn providelp_native(
+        ctx: Context<ProvideLpNative>,
+        lamports: u64,
+        usdc_amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.token_transfer(lamports, usdc_amount)?;
+        msg!("providing liquidty with native sol is successfull");
+        Ok(())
+    }
+
+    pub fn swap_sol_to_token(ctx: Context<SwapSolToToken>, lamports: u64) -> Result<()> {
+        ctx.accounts.main_swap_function(lamports)?;
+        msg!("native sol swap is working");
+        Ok(())
+    }
+
+    pub fn swap_token_to_sol(ctx: Context<SwapTokenToSol>, amount_toswap: u64) -> Result<()> {
+        ctx.accounts.main_swap_function(amount_toswap)?;
+        msg!("native sol swap is working");
+        Ok(())
+    }
+
+    pub fn remove_liquidity_native(
+        ctx: Context<RemoveLiquidityNative>,
+        burnamount: u64,
+    ) -> Result<()> {
+        ctx.accounts.remove_lp_main(burnamount)?;
+        msg!("liquidty removed as native sol");
+        Ok(())
+    }
+
+    //lend from one of the vaults, a flash_repay must follow in the same transaction
+    pub fn flash_loan(ctx: Context<FlashLoan>, amount: u64) -> Result<()> {
+        ctx.accounts.flash_loan_main(amount)?;
+        msg!("flash loan issued");
+        Ok(())
+    }
+
+    pub fn flash_repay(ctx: Context<FlashRepay>) -> Result<()> {
+        ctx.accounts.flash_repay_main()?;
+        msg!("flash loan repaid");
+        Ok(())
+    }
+
+    //pay out first, call back into the caller program, then check the invariant
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashSwap<'info>>,
+        amount_out: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let remaining_accounts = ctx.remaining_accounts;
+        ctx.accounts
+            .flash_swap_main(amount_out, data, remaining_accounts)?;
+        msg!("flash swap is working");
+        Ok(())
+    }
+
+    //config admin turns the protocol fee on by passing the lp account, off by leaving it out
+    pub fn set_protocol_fee(ctx: Context<SetProtocolFee>) -> Result<()> {
+        ctx.accounts.set_main()?;
+        msg!("protocol fee updated");
+        Ok(())
+    }
+
+    //stored reserves take the vault balances, donations become part of the price
+    pub fn sync(ctx: Context<SyncReserves>) -> Result<()> {
+        ctx.accounts.sync_main();
+        msg!("reserves synced");
+        Ok(())
+    }
+
+    //vault balances above the stored reserves go to the recipient
+    pub fn skim(ctx: Context<SkimReserves>) -> Result<()> {
+        ctx.accounts.skim_main()?;
+        msg!("excess skimmed");
+        Ok(())
+    }
+
+    //admin or creator closes a drained pool, the rent and the reserves behind the locked
+    //minimum go back to the creator
+    pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
+        ctx.accounts.close_main()?;
+        msg!("pool closed");
+        Ok(())
+    }
+
+    //reallocs a pool written by an older layout and brings it to POOL_VERSION, pools from before
+    //fee tiers keep their address and sign with the old seeds
+    pub fn migrate_pool(ctx: Context<MigratePool>) -> Result<()> {
+        ctx.accounts.migrate_main()?;
+        msg!("pool migrated to version {}", POOL_VERSION);
+        Ok(())
+    }
+
+    //moves liquidity to another pool of the pair, what the target ratio can not take is returned
+    pub fn migrate_liquidity(
+        ctx: Context<MigrateLiquidity>,
+        burn_amount: u64,
+        min_usdc_out: u64,
+        min_wsol_out: u64,
+        min_lp_out: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .migrate_liquidity_main(burn_amount, min_usdc_out, min_wsol_out, min_lp_out)?;
+        msg!("liquidity migrated");
+        Ok(())
+    }
+
+    //claimable fee pools, the checkpoint starts earning from the current fee growth
+    pub fn open_fee_checkpoint(ctx: Context<OpenFeeCheckpoint>) -> Result<()> {
+        ctx.accounts.open_main(ctx.bumps.fee_checkpoint)?;
+        msg!("fee checkpoint opened");
+        Ok(())
+    }
+
+    //pay out the accrued swap fees, lp tokens are not touched
+    pub fn claim_fees(ctx: Context<ClaimFees>) -> Result<()> {
+        ctx.accounts.claim_main()?;
+        msg!("fees claimed");
+        Ok(())
+    }
+
+    //concentrated liquidity pools, separate from the full range pools above
+    pub fn initialize_clmm_pool(
+        ctx: Context<InitializeClmmPool>,
+        tick_spacing: u16,
+        fee_rate: u16,
+        initial_sqrt_price_x64: u128,
+    ) -> Result<()> {
+        ctx.accounts.initialize_main(
+            tick_spacing,
+            fee_rate,
+            initial_sqrt_price_x64,
+            ctx.bumps.clmm_pool,
+        )?;
+
+        //creation fee and the registry entry, fee_rate has to be a config fee tier
+        register_new_pool(
+            &mut ctx.accounts.amm_config,
+            &mut ctx.accounts.registry_page,
+            ctx.bumps.registry_page,
+            ctx.accounts.clmm_pool.key(),
+            CLMM_CURVE_BIT,
+            fee_rate,
+            &ctx.accounts.payer,
+            &ctx.accounts.fee_receiver,
+            &ctx.accounts.system_program,
+        )?;
+        msg!("clmm pool initialized");
+        Ok(())
+    }
+
+    pub fn initialize_tick_array(
+        ctx: Context<InitializeTickArray>,
+        start_tick_index: i32,
+    ) -> Result<()> {
+        ctx.accounts.initialize_main(start_tick_index)?;
+        msg!("tick array initialized");
+        Ok(())
+    }
+
+    pub fn open_position(
+        ctx: Context<OpenPosition>,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<()> {
+        ctx.accounts
+            .open_main(tick_lower, tick_upper, ctx.bumps.position)?;
+        msg!("position opened");
+        Ok(())
+    }
+
+    pub fn increase_liquidity(
+        ctx: Context<ModifyClmmLiquidity>,
+        liquidity: u128,
+        amount_usdc_max: u64,
+        amount_wsol_max: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .increase_main(liquidity, amount_usdc_max, amount_wsol_max)?;
+        msg!("position liquidity increased");
+        Ok(())
+    }
+
+    pub fn decrease_liquidity(
+        ctx: Context<ModifyClmmLiquidity>,
+        liquidity: u128,
+        amount_usdc_min: u64,
+        amount_wsol_min: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .decrease_main(liquidity, amount_usdc_min, amount_wsol_min)?;
+        msg!("position liquidity decreased");
+        Ok(())
+    }
+
+    pub fn collect_position_fees(ctx: Context<ModifyClmmLiquidity>) -> Result<()> {
+        ctx.accounts.collect_main()?;
+        msg!("position fees collected");
+        Ok(())
+    }
+
+    //tick arrays the swap walks through are passed in remaining accounts, in swap order
+    pub fn swap_clmm<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapClmm<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        let remaining_accounts = ctx.remaining_accounts;
+        ctx.accounts
+            .swap_main(amount_in, min_amount_out, remaining_accounts)?;
+        msg!("clmm swap is working");
+        Ok(())
+    }
 }
 
 #[account]
@@ -55,14 +414,335 @@ pub struct LpPoolAccountShape {
     pub wsol_vault_address: Pubkey,
     pub lp_token_mint: Pubkey,
     pub bump: u8,
+    //set while a flash loan is open
+    pub locked: bool,
+    pub flash_loan_vault: Pubkey,
+    pub flash_loan_amount: u64,
+    //pricing curve and its parameter
+    pub curve_type: CurveType,
+    pub curve_parameter: u64,
+    //swap fee in basis points, one of the config fee tiers and part of the pool seeds
+    pub fee_tier_bps: u16,
+    //how swap fees reach the lps
+    pub fee_mode: FeeMode,
+    //claimable mode only, swap fees per lp token since the pool was created, Q64.64
+    pub fee_growth_usdc_per_lp_x64: u128,
+    pub fee_growth_wsol_per_lp_x64: u128,
+    //fees sitting in the vaults that are not part of the reserves
+    pub unclaimed_usdc_fees: u64,
+    pub unclaimed_wsol_fees: u64,
+    //signer of the pool creation, can close the pool once it is drained
+    pub creator: Pubkey,
+    //lp account receiving the protocol fee, default means the fee is off
+    pub protocol_fee_account: Pubkey,
+    //reserve product after the last liquidity event while the protocol fee is on
+    pub k_last: u128,
+    //reserves the curve prices against, tokens sent straight to a vault are not counted
+    pub usdc_reserve: u64,
+    pub wsol_reserve: u64,
+    //layout version, pools created before versioning read as 0 until migrate_pool
+    pub version: u8,
+    //claimable mode only, lp held in checkpointed accounts, the only lp that can claim fees
+    pub checkpointed_lp_supply: u64,
+    //pools created before fee tiers sign without the fee tier seed
+    pub legacy_seeds: bool,
+    //claimable mode only, part of the unclaimed fees moved into checkpoints and not claimed yet,
+    //the rest of the unclaimed fees is rounding dust no lp can claim
+    pub owed_usdc_fees: u64,
+    pub owed_wsol_fees: u64,
+    //room for future fields, new fields are carved from the front so the size stays put
+    pub reserved: [u8; POOL_RESERVED_BYTES],
+}
+
+//bump when a field is added, migrate_pool brings older pools up to it
+pub const POOL_VERSION: u8 = 1;
+pub const POOL_RESERVED_BYTES: usize = 103;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum FeeMode {
+    //fees stay in the reserves and grow the value of every lp token
+    Compound,
+    //fees of swap and the native swaps are kept out of the reserves and paid by claim_fees,
+    //flash loan, routed and zap fees still compound
+    Claimable,
+}
+
+//lp kept back from the first deposit and minted to the locked lp account, so the share
+//price can not start at a value a donation could inflate
+pub const MINIMUM_LIQUIDITY: u64 = 1000;
+
+//mints MINIMUM_LIQUIDITY on the first deposit, the locked account has no way out
+fn lock_minimum_liquidity<'info>(
+    pool: &Account<'info, LpPoolAccountShape>,
+    lp_mint: &mut InterfaceAccount<'info, Mint>,
+    locked_lp_account: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    if lp_mint.supply != 0 {
+        return Ok(());
+    }
+
+    let cpi_accounts = MintTo {
+        mint: lp_mint.to_account_info(),
+        to: locked_lp_account.to_account_info(),
+        authority: pool.to_account_info(),
+    };
+    pool.with_signer_seeds(|signer_seeds| {
+        let cpi_context = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        token_interface::mint_to(cpi_context, MINIMUM_LIQUIDITY)
+    })?;
+
+    lp_mint.reload()?;
+    Ok(())
+}
+
+impl LpPoolAccountShape {
+    //fields of a new pool, addresses are [usdc_mint, wsol_mint, usdc_vault, wsol_vault, lp_mint]
+    //and pool_settings is (curve_type, curve_parameter, fee_tier_bps)
+    pub fn setup(
+        &mut self,
+        bump: u8,
+        [usdc_mint, wsol_mint, usdc_vault, wsol_vault, lp_token_mint]: [Pubkey; 5],
+        creator: Pubkey,
+        (curve_type, curve_parameter, fee_tier_bps): (CurveType, u64, u16),
+        fee_mode: FeeMode,
+    ) -> Result<()> {
+        if !curve_type.is_valid_parameter(curve_parameter) {
+            return err!(CurveErrors::InvalidCurveParameter);
+        }
+
+        self.bump = bump;
+        self.usdc_mint = usdc_mint;
+        self.wsol_mint = wsol_mint;
+        self.usdc_vault_address = usdc_vault;
+        self.wsol_vault_address = wsol_vault;
+        self.lp_token_mint = lp_token_mint;
+        self.curve_type = curve_type;
+        self.curve_parameter = curve_parameter;
+        self.fee_tier_bps = fee_tier_bps;
+        self.fee_mode = fee_mode;
+        self.creator = creator;
+        self.version = POOL_VERSION;
+        Ok(())
+    }
+
+    //runs f with the seeds the pool signs its cpis with
+    pub fn with_signer_seeds<T>(&self, f: impl FnOnce(&[&[&[u8]]]) -> T) -> T {
+        if self.legacy_seeds {
+            let seeds: [&[u8]; 4] = [
+                b"pool_state_v3",
+                self.usdc_mint.as_ref(),
+                self.wsol_mint.as_ref(),
+                &[self.bump],
+            ];
+            return f(&[&seeds[..]]);
+        }
+        let fee_tier = self.fee_tier_bps.to_le_bytes();
+        let seeds: [&[u8]; 5] = [
+            b"pool_state_v3",
+            self.usdc_mint.as_ref(),
+            self.wsol_mint.as_ref(),
+            &fee_tier,
+            &[self.bump],
+        ];
+        f(&[&seeds[..]])
+    }
+
+    pub fn curve(&self) -> Box<dyn Curve> {
+        curve::build(self.curve_type, self.curve_parameter, self.fee_tier_bps)
+    }
+
+    //input left for the curve once the pool fee is taken
+    pub fn amount_after_fee(&self, amount_in: u64) -> u64 {
+        curve::amount_after_fee(amount_in, self.fee_tier_bps)
+    }
+
+    //lp for a deposit on the pool curve, the first deposit keeps back MINIMUM_LIQUIDITY
+    pub fn deposit_lp(
+        &self,
+        usdc_amount: u64,
+        wsol_amount: u64,
+        usdc_reserve: u64,
+        wsol_reserve: u64,
+        total_supply: u64,
+    ) -> Result<u64> {
+        let liquidity = self
+            .curve()
+            .deposit(
+                usdc_amount,
+                wsol_amount,
+                usdc_reserve,
+                wsol_reserve,
+                total_supply,
+            )
+            .ok_or(CurveErrors::CurveMathError)?;
+
+        if total_supply != 0 {
+            return Ok(liquidity);
+        }
+
+        //deposit should satisfy the amount
+        if liquidity <= MINIMUM_LIQUIDITY {
+            return err!(ProvideLpErrors::LiquidityTooLow);
+        }
+        Ok(liquidity - MINIMUM_LIQUIDITY)
+    }
+
+    //lp for a deposit against the stored reserves of the vaults
+    pub fn deposit_lp_at_reserves(
+        &self,
+        usdc_amount: u64,
+        wsol_amount: u64,
+        usdc_vault: &InterfaceAccount<TokenAccount>,
+        wsol_vault: &InterfaceAccount<TokenAccount>,
+        total_supply: u64,
+    ) -> Result<u64> {
+        self.deposit_lp(
+            usdc_amount,
+            wsol_amount,
+            self.reserve(usdc_vault),
+            self.reserve(wsol_vault),
+            total_supply,
+        )
+    }
+
+    //output on the pool curve for an input that already had the fee taken
+    pub fn swap_output(
+        &self,
+        input_amount: u64,
+        input_vault: &InterfaceAccount<TokenAccount>,
+        output_vault: &InterfaceAccount<TokenAccount>,
+    ) -> Result<u64> {
+        let outputamount = self
+            .curve()
+            .swap(
+                input_amount,
+                self.reserve(input_vault),
+                self.reserve(output_vault),
+                self.direction(input_vault.key()),
+            )
+            .ok_or(SwapTokenErrors::SwapError)?;
+        Ok(outputamount)
+    }
+
+    //stored reserve of the vault, only moved by the program through credit and debit
+    pub fn reserve(&self, vault: &InterfaceAccount<TokenAccount>) -> u64 {
+        if vault.key() == self.usdc_vault_address {
+            self.usdc_reserve
+        } else if vault.key() == self.wsol_vault_address {
+            self.wsol_reserve
+        } else {
+            0
+        }
+    }
+
+    fn reserve_mut(&mut self, vault: Pubkey) -> Result<&mut u64> {
+        if vault == self.usdc_vault_address {
+            Ok(&mut self.usdc_reserve)
+        } else if vault == self.wsol_vault_address {
+            Ok(&mut self.wsol_reserve)
+        } else {
+            err!(ReserveErrors::InvalidVault)
+        }
+    }
+
+    //tokens that came into the vault through the program
+    pub fn credit_reserve(&mut self, vault: Pubkey, amount: u64) -> Result<()> {
+        let reserve = self.reserve_mut(vault)?;
+        *reserve = reserve
+            .checked_add(amount)
+            .ok_or(ProvideLpErrors::MultiplicationError)?;
+        Ok(())
+    }
+
+    //tokens that left the vault through the program
+    pub fn debit_reserve(&mut self, vault: Pubkey, amount: u64) -> Result<()> {
+        let reserve = self.reserve_mut(vault)?;
+        *reserve = reserve
+            .checked_sub(amount)
+            .ok_or(ReserveErrors::ReserveUnderflow)?;
+        Ok(())
+    }
+
+    //vault balance above the reserve and the fees waiting to be claimed
+    pub fn excess(&self, vault: &InterfaceAccount<TokenAccount>) -> u64 {
+        let unclaimed = if vault.key() == self.usdc_vault_address {
+            self.unclaimed_usdc_fees
+        } else {
+            self.unclaimed_wsol_fees
+        };
+        vault
+            .amount
+            .saturating_sub(self.reserve(vault).saturating_add(unclaimed))
+    }
+
+    //claimable pools move the fee out of the reserves and spread it over the checkpointed lp,
+    //the share of lp that can never claim (locked, protocol fee, unchecked) stays in the reserves
+    pub fn accrue_swap_fee(&mut self, input_vault: Pubkey, fee: u64, lp_supply: u64) -> Result<()> {
+        let claimable_supply = self.checkpointed_lp_supply.min(lp_supply);
+        if self.fee_mode != FeeMode::Claimable || fee == 0 || claimable_supply == 0 {
+            return Ok(());
+        }
+
+        //fee share of the checkpointed lp, rounded down
+        let fee = math::mul_div(fee as u128, claimable_supply as u128, lp_supply as u128)
+            .and_then(|share| u64::try_from(share).ok())
+            .ok_or(ProvideLpErrors::MultiplicationError)?;
+        let growth = math::mul_div(fee as u128, math::Q64, claimable_supply as u128)
+            .ok_or(ProvideLpErrors::MultiplicationError)?;
+        self.debit_reserve(input_vault, fee)?;
+        if input_vault == self.usdc_vault_address {
+            self.fee_growth_usdc_per_lp_x64 = self.fee_growth_usdc_per_lp_x64.wrapping_add(growth);
+            self.unclaimed_usdc_fees = self
+                .unclaimed_usdc_fees
+                .checked_add(fee)
+                .ok_or(ProvideLpErrors::MultiplicationError)?;
+        } else {
+            self.fee_growth_wsol_per_lp_x64 = self.fee_growth_wsol_per_lp_x64.wrapping_add(growth);
+            self.unclaimed_wsol_fees = self
+                .unclaimed_wsol_fees
+                .checked_add(fee)
+                .ok_or(ProvideLpErrors::MultiplicationError)?;
+        }
+        Ok(())
+    }
+
+    //usdc in is a to b
+    pub fn direction(&self, input_vault: Pubkey) -> TradeDirection {
+        if input_vault == self.usdc_vault_address {
+            TradeDirection::AtoB
+        } else {
+            TradeDirection::BtoA
+        }
+    }
+}
+
+#[error_code]
+pub enum CurveErrors {
+    #[msg("curve parameter is out of range")]
+    InvalidCurveParameter,
+
+    #[msg("instruction does not support this pool curve")]
+    UnsupportedCurve,
+
+    #[msg("curve math failed")]
+    CurveMathError,
 }
 
 #[derive(Accounts)]
+#[instruction(curve_type: CurveType, curve_parameter: u64, fee_mode: FeeMode, fee_tier_bps: u16)]
 pub struct Initialize<'info> {
-    //signer
-    #[account(mut)]
+    //signer, creator of the pool
     pub signer: Signer<'info>,
 
+    //pays the rent and the creation fee, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
     //mint account for the tokens
     pub usdc_mint: InterfaceAccount<'info, Mint>,
     pub wsol_mint: InterfaceAccount<'info, Mint>,
@@ -70,24 +750,159 @@ pub struct Initialize<'info> {
     //system program field
     pub system_program: Program<'info, System>,
     //account init
-    #[account(init , payer = signer, space = 8+LpPoolAccountShape::INIT_SPACE, seeds = [b"pool_state_v3", usdc_mint.key().as_ref(), wsol_mint.key().as_ref()], bump)]
+    #[account(init , payer = payer, space = 8+LpPoolAccountShape::INIT_SPACE, seeds = [b"pool_state_v3", usdc_mint.key().as_ref(), wsol_mint.key().as_ref(), fee_tier_bps.to_le_bytes().as_ref()], bump)]
     pub pool_stateaccount: Account<'info, LpPoolAccountShape>,
 
     //token program
     pub token_program: Interface<'info, TokenInterface>,
     //create usdc_vault
-    #[account(init, payer = signer, token::mint= usdc_mint, token::authority = pool_stateaccount, token::token_program  = token_program, seeds = [b"usdc_vault_v3",usdc_mint.key().as_ref()], bump)]
+    #[account(init, payer = payer, token::mint= usdc_mint, token::authority = pool_stateaccount, token::token_program  = token_program, seeds = [b"usdc_vault_v3", pool_stateaccount.key().as_ref()], bump)]
     pub usdc_vault: InterfaceAccount<'info, TokenAccount>,
 
     //create sol_vault
-    #[account(init, payer = signer, token::mint= wsol_mint, token::authority = pool_stateaccount, token::token_program  = token_program, seeds = [b"sol_vault_v3",wsol_mint.key().as_ref()], bump)]
+    #[account(init, payer = payer, token::mint= wsol_mint, token::authority = pool_stateaccount, token::token_program  = token_program, seeds = [b"sol_vault_v3", pool_stateaccount.key().as_ref()], bump)]
     pub wsol_vault: InterfaceAccount<'info, TokenAccount>,
     //adding lp mint logic
     //signer for the account
 
-    //the authority of this mint should be the contract
-    #[account(init, payer = signer, mint::decimals = 9, mint::authority = pool_stateaccount, mint::freeze_authority = pool_stateaccount)]
+    //the authority of this mint should be the contract, derived from the pool so clients can find it
+    //the token program can not close a mint, a pool re-created after close_pool takes over the empty one
+    #[account(init_if_needed, payer = payer, mint::decimals = 9, mint::authority = pool_stateaccount, mint::freeze_authority = pool_stateaccount, seeds = [b"lp_mint_v3", pool_stateaccount.key().as_ref()], bump, constraint = mint.supply == 0 @ ClosePoolErrors::LpSupplyLeft)]
     pub mint: InterfaceAccount<'info, Mint>,
+
+    //holds MINIMUM_LIQUIDITY, no instruction moves lp out of it
+    #[account(init, payer = payer, token::mint = mint, token::authority = pool_stateaccount, token::token_program = token_program, seeds = [b"locked_lp_v3", pool_stateaccount.key().as_ref()], bump)]
+    pub locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //shared settings, the curve has to be allowed there
+    #[account(mut, seeds = [b"amm_config_v3"], bump = amm_config.bump)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+
+    //registry page the pool is added to, see register_new_pool
+    #[account(init_if_needed, payer = payer, space = 8 + PoolRegistryPageAccountShape::INIT_SPACE, seeds = [b"pool_registry_v3", (amm_config.pool_count / POOLS_PER_REGISTRY_PAGE).to_le_bytes().as_ref()], bump)]
+    pub registry_page: Box<Account<'info, PoolRegistryPageAccountShape>>,
+
+    /// CHECK: only receives the creation fee, checked against the config
+    #[account(mut, address = amm_config.fee_receiver @ ConfigErrors::InvalidFeeReceiver)]
+    pub fee_receiver: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(curve_type: CurveType, curve_parameter: u64, fee_tier_bps: u16)]
+pub struct InitializeWithLiquidity<'info> {
+    pub signer: Signer<'info>,
+
+    //pays the rent and the creation fee, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //first deposit comes from here
+    #[account(mut, token::mint = usdc_mint, token::authority = signer)]
+    pub user_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = wsol_mint, token::authority = signer)]
+    pub user_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //same accounts as initialize
+    #[account(init, payer = payer, space = 8 + LpPoolAccountShape::INIT_SPACE, seeds = [b"pool_state_v3", usdc_mint.key().as_ref(), wsol_mint.key().as_ref(), fee_tier_bps.to_le_bytes().as_ref()], bump)]
+    pub pool_stateaccount: Box<Account<'info, LpPoolAccountShape>>,
+    #[account(init, payer = payer, token::mint = usdc_mint, token::authority = pool_stateaccount, token::token_program = token_program, seeds = [b"usdc_vault_v3", pool_stateaccount.key().as_ref()], bump)]
+    pub usdc_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(init, payer = payer, token::mint = wsol_mint, token::authority = pool_stateaccount, token::token_program = token_program, seeds = [b"sol_vault_v3", pool_stateaccount.key().as_ref()], bump)]
+    pub wsol_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(init_if_needed, payer = payer, mint::decimals = 9, mint::authority = pool_stateaccount, mint::freeze_authority = pool_stateaccount, seeds = [b"lp_mint_v3", pool_stateaccount.key().as_ref()], bump, constraint = mint.supply == 0 @ ClosePoolErrors::LpSupplyLeft)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(init, payer = payer, token::mint = mint, token::authority = pool_stateaccount, token::token_program = token_program, seeds = [b"locked_lp_v3", pool_stateaccount.key().as_ref()], bump)]
+    pub locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //creator lp account, the canonical ata like providelp
+    #[account(init_if_needed, payer = payer, associated_token::mint = mint, associated_token::authority = signer, associated_token::token_program = token_program)]
+    pub lp_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+
+    //shared settings, the curve has to be allowed there
+    #[account(mut, seeds = [b"amm_config_v3"], bump = amm_config.bump)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+
+    //registry page the pool is added to, see register_new_pool
+    #[account(init_if_needed, payer = payer, space = 8 + PoolRegistryPageAccountShape::INIT_SPACE, seeds = [b"pool_registry_v3", (amm_config.pool_count / POOLS_PER_REGISTRY_PAGE).to_le_bytes().as_ref()], bump)]
+    pub registry_page: Box<Account<'info, PoolRegistryPageAccountShape>>,
+
+    /// CHECK: only receives the creation fee, checked against the config
+    #[account(mut, address = amm_config.fee_receiver @ ConfigErrors::InvalidFeeReceiver)]
+    pub fee_receiver: UncheckedAccount<'info>,
+}
+
+impl<'info> InitializeWithLiquidity<'info> {
+    fn init_main(&mut self, usdc_amount: u64, wsol_amount: u64) -> Result<()> {
+        //first deposit, the amounts set the price
+        let lp_amount = self
+            .pool_stateaccount
+            .deposit_lp(usdc_amount, wsol_amount, 0, 0, 0)?;
+        self.transfer_in(
+            &self.usdc_mint,
+            &self.user_usdc_account,
+            &self.usdc_vault,
+            usdc_amount,
+        )?;
+        self.transfer_in(
+            &self.wsol_mint,
+            &self.user_wsol_account,
+            &self.wsol_vault,
+            wsol_amount,
+        )?;
+        let pool = &mut self.pool_stateaccount;
+        pool.usdc_reserve = usdc_amount;
+        pool.wsol_reserve = wsol_amount;
+
+        lock_minimum_liquidity(
+            &self.pool_stateaccount,
+            &mut self.mint,
+            &self.locked_lp_account,
+            &self.token_program,
+        )?;
+        self.mint_lptokens(lp_amount)
+    }
+
+    fn transfer_in(
+        &self,
+        mint: &InterfaceAccount<'info, Mint>,
+        from: &InterfaceAccount<'info, TokenAccount>,
+        vault: &InterfaceAccount<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: mint.to_account_info(),
+            from: from.to_account_info(),
+            to: vault.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_context, amount, mint.decimals)?;
+        Ok(())
+    }
+
+    fn mint_lptokens(&self, amount: u64) -> Result<()> {
+        let cpi_accounts = MintTo {
+            mint: self.mint.to_account_info(),
+            to: self.lp_ata.to_account_info(),
+            authority: self.pool_stateaccount.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_stateaccount.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::mint_to(cpi_context, amount)
+        })?;
+        Ok(())
+    }
 }
 
 #[error_code]
@@ -97,17 +912,35 @@ pub enum ProvideLpErrors {
 
     #[msg("liquidity too low")]
     LiquidityTooLow,
+
+    #[msg("token is not part of the pool")]
+    InvalidToken,
+
+    #[msg("pool has no liquidity yet")]
+    PoolNotSeeded,
+
+    #[msg("lp amount is below the minimum")]
+    SlippageExceeded,
 }
 
 //from the token program
 #[derive(Accounts)]
 pub struct ProvideLp<'info> {
     //tranfer the money from
-    #[account(mut)]
     pub signer: Signer<'info>,
 
+    //owner of the lp, the signer itself or an end user or strategy pda
+    /// CHECK: only the authority of the lp account, never read or written
+    pub beneficiary: UncheckedAccount<'info>,
+
+    //pays the rent of the lp account
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
     //mints for the vaults
+    #[account(address = mint_authority.usdc_mint)]
     pub usdc_mint: InterfaceAccount<'info, Mint>,
+    #[account(address = mint_authority.wsol_mint)]
     pub wsol_mint: InterfaceAccount<'info, Mint>,
 
     //user token account
@@ -117,9 +950,9 @@ pub struct ProvideLp<'info> {
     pub user_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     //vault accounts
-    #[account(mut, token::mint = usdc_mint, token::authority = mint_authority)]
+    #[account(mut, address = mint_authority.usdc_vault_address)]
     pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account(mut, token::mint = wsol_mint, token::authority = mint_authority)]
+    #[account(mut, address = mint_authority.wsol_vault_address)]
     pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     //token_program
@@ -128,17 +961,31 @@ pub struct ProvideLp<'info> {
     // ---------minting lp token logic --------
     //token account creation----
     //mint of the lp
-    #[account(mut)]
+    #[account(mut, address = mint_authority.lp_token_mint)]
     pub lptokenmint: InterfaceAccount<'info, Mint>,
     //account creation
-    #[account(init_if_needed, payer = signer, token::mint = lptokenmint, token::authority = signer, token::token_program = token_program, seeds = [b"lptokenata_v3", signer.key().as_ref()], bump)]
+    #[account(init_if_needed, payer = payer, associated_token::mint = lptokenmint, associated_token::authority = beneficiary, associated_token::token_program = token_program)]
     pub lp_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 
     //for minting lp tokens -------
     //pool state acount for getting seeds
     //mining authority
+    #[account(mut, constraint = !mint_authority.locked @ FlashLoanErrors::PoolLocked)]
     pub mint_authority: Box<Account<'info, LpPoolAccountShape>>,
+
+    //claimable pools only, see checkpoint_before_lp_change
+    #[account(mut, seeds = [b"fee_checkpoint_v3", mint_authority.key().as_ref(), beneficiary.key().as_ref()], bump = fee_checkpoint.bump, constraint = fee_checkpoint.lp_account == lp_ata.key() @ FeeErrors::InvalidLpAccount)]
+    pub fee_checkpoint: Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+
+    //needed while the protocol fee is on, see mint_protocol_fee_lp
+    #[account(mut, address = mint_authority.protocol_fee_account @ ProtocolFeeErrors::InvalidFeeAccount)]
+    pub protocol_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    //receives MINIMUM_LIQUIDITY on the first deposit
+    #[account(mut, seeds = [b"locked_lp_v3", mint_authority.key().as_ref()], bump)]
+    pub locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
     ////user ata account
     //#[account(mut,token::authority= signer, token::mint = lptokenmint)]
     //pub lpata: InterfaceAccount<'info, TokenAccount>,
@@ -146,16 +993,39 @@ pub struct ProvideLp<'info> {
 
 impl<'info> ProvideLp<'info> {
     //providing lp mainly has signing function
-    fn token_transfer(&self, wsol_amount: u64, usdc_amount: u64) -> Result<()> {
+    fn token_transfer(&mut self, wsol_amount: u64, usdc_amount: u64) -> Result<()> {
+        //protocol fee first, the deposit is priced against the supply after it
+        mint_protocol_fee_lp(
+            &self.mint_authority,
+            &mut self.lptokenmint,
+            &self.protocol_fee_account,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            &self.token_program,
+        )?;
+
         //calculate the lp token amount need to provide
-        let lp_amount = self.lptoken_amount(usdc_amount, wsol_amount).unwrap();
+        let lp_amount = self.lptoken_amount(usdc_amount, wsol_amount)?;
 
         //tranfer function for usdc and sol
         self.tranfer_usdc(usdc_amount)?;
         self.tranfer_wsol(wsol_amount)?;
 
         //min lp token function
-        self.mint_lptokens(lp_amount as u64)?;
+        lock_minimum_liquidity(
+            &self.mint_authority,
+            &mut self.lptokenmint,
+            &self.locked_lp_account,
+            &self.token_program,
+        )?;
+        self.mint_lptokens(lp_amount)?;
+        let (usdc_vault, wsol_vault) =
+            (self.usdc_vault_account.key(), self.wsol_vault_account.key());
+        self.mint_authority
+            .credit_reserve(usdc_vault, usdc_amount)?;
+        self.mint_authority
+            .credit_reserve(wsol_vault, wsol_amount)?;
+        update_k_last(&mut self.mint_authority);
         Ok(())
     }
 
@@ -194,76 +1064,139 @@ impl<'info> ProvideLp<'info> {
     }
 
     fn lptoken_amount(&self, usdc_amount: u64, wsol_amount: u64) -> Result<u64> {
-        //burn constant to prevent inflation attack
-        const MINIMUM_LIQUIDITY: u64 = 1000;
+        self.mint_authority.deposit_lp_at_reserves(
+            usdc_amount,
+            wsol_amount,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            self.lptokenmint.supply,
+        )
+    }
 
-        //lp share on the basis of total lp supply
-        let total_supply = self.lptokenmint.supply;
+    fn single_sided_transfer(&mut self, token: Pubkey, amount: u64, min_lp_out: u64) -> Result<()> {
+        //the optimal swap amount is solved for x * y = k only
+        if self.mint_authority.curve_type != CurveType::ConstantProduct {
+            return err!(CurveErrors::UnsupportedCurve);
+        }
 
-        //the lp amount
-        let lp_amount: u64;
+        mint_protocol_fee_lp(
+            &self.mint_authority,
+            &mut self.lptokenmint,
+            &self.protocol_fee_account,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            &self.token_program,
+        )?;
 
+        let total_supply = self.lptokenmint.supply;
         if total_supply == 0 {
-            let (lp, _) = self
-                .first_time_amount(usdc_amount, wsol_amount, MINIMUM_LIQUIDITY)
-                .unwrap();
-            lp_amount = lp;
-        } else {
-            let (lp, _) = self.normal_amount(usdc_amount, wsol_amount).unwrap();
-            lp_amount = lp
+            return err!(ProvideLpErrors::PoolNotSeeded);
         }
 
-        Ok(lp_amount)
-    }
-
-    fn first_time_amount(
-        &self,
-        usdc_amount: u64,
-        wsol_amount: u64,
-        minimum_liquidity: u64,
-    ) -> Result<(u64, u64)> {
-        let product = (usdc_amount as u128)
-            .checked_mul(wsol_amount as u128)
-            .ok_or(ProvideLpErrors::MultiplicationError)?;
+        //reserves in the direction of the deposit
+        let usdc_in = if token == self.usdc_mint.key() {
+            true
+        } else if token == self.wsol_mint.key() {
+            false
+        } else {
+            return err!(ProvideLpErrors::InvalidToken);
+        };
+        let (reserve_in, reserve_out) = if usdc_in {
+            (
+                self.mint_authority.reserve(&self.usdc_vault_account),
+                self.mint_authority.reserve(&self.wsol_vault_account),
+            )
+        } else {
+            (
+                self.mint_authority.reserve(&self.wsol_vault_account),
+                self.mint_authority.reserve(&self.usdc_vault_account),
+            )
+        };
 
-        let liquidity = (product as f64).sqrt() as u64;
+        //swap part of the deposit, the output never leaves the vault
+        let pool_curve = self.mint_authority.curve();
+        let direction = if usdc_in {
+            TradeDirection::AtoB
+        } else {
+            TradeDirection::BtoA
+        };
+        let swap_amount = curve::constant_product::zap_swap_amount(
+            reserve_in,
+            amount,
+            self.mint_authority.fee_tier_bps,
+        )
+        .ok_or(CurveErrors::CurveMathError)?;
+        let swap_out = pool_curve
+            .swap(
+                self.mint_authority.amount_after_fee(swap_amount),
+                reserve_in,
+                reserve_out,
+                direction,
+            )
+            .ok_or(SwapTokenErrors::SwapError)?;
 
-        //deposit should satisfy the amount
-        if liquidity <= minimum_liquidity {
+        //deposit the rest against the reserves after the swap
+        let reserve_in_after = reserve_in + swap_amount;
+        let reserve_out_after = reserve_out - swap_out;
+        let (usdc_amount, wsol_amount, usdc_reserve, wsol_reserve) = if usdc_in {
+            (
+                amount - swap_amount,
+                swap_out,
+                reserve_in_after,
+                reserve_out_after,
+            )
+        } else {
+            (
+                swap_out,
+                amount - swap_amount,
+                reserve_out_after,
+                reserve_in_after,
+            )
+        };
+        let lp_amount = pool_curve
+            .deposit(
+                usdc_amount,
+                wsol_amount,
+                usdc_reserve,
+                wsol_reserve,
+                total_supply,
+            )
+            .ok_or(CurveErrors::CurveMathError)?;
+
+        if lp_amount == 0 {
             return err!(ProvideLpErrors::LiquidityTooLow);
         }
+        if lp_amount < min_lp_out {
+            return err!(ProvideLpErrors::SlippageExceeded);
+        }
 
-        return Ok((
-            liquidity.checked_sub(minimum_liquidity).unwrap(),
-            minimum_liquidity,
-        ));
-    }
-
-    fn normal_amount(&self, usdc_amount: u64, wsol_amount: u64) -> Result<(u64, u64)> {
-        let total_supply = self.lptokenmint.supply;
-
-        //share on the basis of usdc
-        let share_usdc = (usdc_amount as u128)
-            .checked_mul(total_supply as u128)
-            .ok_or(ProvideLpErrors::MultiplicationError)?
-            .checked_div(self.usdc_vault_account.amount as u128)
-            .ok_or(ProvideLpErrors::MultiplicationError)?;
-
-        //share on the basis of wsol
-        let share_wsol = (wsol_amount as u128)
-            .checked_mul(total_supply as u128)
-            .ok_or(ProvideLpErrors::MultiplicationError)?
-            .checked_div(self.wsol_vault_account.amount as u128)
-            .ok_or(ProvideLpErrors::MultiplicationError)?;
-
-        //take the smaller share from both the values
-        let liquidity = std::cmp::min(share_usdc, share_wsol) as u64;
+        //the whole amount goes in, rounding dust stays with the pool
+        if usdc_in {
+            self.tranfer_usdc(amount)?;
+        } else {
+            self.tranfer_wsol(amount)?;
+        }
 
-        //return
-        return Ok((liquidity, 0));
+        self.mint_lptokens(lp_amount)?;
+        let input_vault = if usdc_in {
+            self.usdc_vault_account.key()
+        } else {
+            self.wsol_vault_account.key()
+        };
+        self.mint_authority.credit_reserve(input_vault, amount)?;
+        update_k_last(&mut self.mint_authority);
+        Ok(())
     }
 
-    fn mint_lptokens(&self, amount: u64) -> Result<()> {
+    fn mint_lptokens(&mut self, amount: u64) -> Result<()> {
+        checkpoint_before_lp_change(
+            &mut self.mint_authority,
+            &mut self.fee_checkpoint,
+            &self.lptokenmint,
+            &self.lp_ata,
+            &self.token_program,
+        )?;
+
         let cpi_accounts = MintTo {
             mint: self.lptokenmint.to_account_info(),
             to: self.lp_ata.to_account_info(),
@@ -271,21 +1204,18 @@ impl<'info> ProvideLp<'info> {
         };
 
         let cpi_program = self.token_program.to_account_info();
-        let usdc_mint = self.usdc_mint.key();
-        let wsol_mint = self.wsol_mint.key();
-        let bump = self.mint_authority.bump;
-        let seeds = [
-            b"pool_state_v3",
-            usdc_mint.as_ref(),
-            wsol_mint.as_ref(),
-            &[bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
-
-        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+        self.mint_authority.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::mint_to(cpi_context, amount)
+        })?;
 
-        token_interface::mint_to(cpi_context, amount)?;
-        Ok(())
+        checkpoint_after_lp_change(
+            &mut self.mint_authority,
+            &mut self.fee_checkpoint,
+            &self.lptokenmint,
+            &mut self.lp_ata,
+            &self.token_program,
+        )
     }
 }
 
@@ -299,10 +1229,10 @@ pub struct SwapTokens<'info> {
     pub input_mint: InterfaceAccount<'info, Mint>,
     pub output_mint: InterfaceAccount<'info, Mint>,
 
-    //user accounts
+    //user accounts, the output can go to any account of the output mint
     #[account(mut, token::authority = signer)]
     pub user_input_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut, token::authority = signer)]
+    #[account(mut, token::mint = output_mint)]
     pub user_output_account: InterfaceAccount<'info, TokenAccount>,
 
     //vaults for the transaction
@@ -312,8 +1242,12 @@ pub struct SwapTokens<'info> {
     pub output_vault_account: InterfaceAccount<'info, TokenAccount>,
 
     //pool state for the vault
+    #[account(mut, has_one = lp_token_mint, constraint = !pool_stateaccount.locked @ FlashLoanErrors::PoolLocked)]
     pub pool_stateaccount: Account<'info, LpPoolAccountShape>,
 
+    //lp supply for the claimable fee accounting
+    pub lp_token_mint: InterfaceAccount<'info, Mint>,
+
     //token program
     pub token_program: Interface<'info, TokenInterface>,
 }
@@ -334,9 +1268,23 @@ pub enum SwapTokenErrors {
     SwapError,
 }
 
+#[event]
+pub struct SwapEvent {
+    pub pool: Pubkey,
+    pub signer: Pubkey,
+    //token account the output was sent to and its owner
+    pub recipient_account: Pubkey,
+    pub recipient: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+}
+
 //impl  for swap
 impl<'info> SwapTokens<'info> {
-    pub fn main_swap_function(&self, amount_toswap: u64) -> Result<()> {
+    pub fn main_swap_function(&mut self, amount_toswap: u64) -> Result<()> {
         //run the checks
         self.checks(amount_toswap)?;
 
@@ -346,8 +1294,31 @@ impl<'info> SwapTokens<'info> {
         //calculate output amount
         let output_amount = self.output_amount_calculation(input_amount)?;
 
-        //call the swap function
-        self.swaptokens(input_amount, output_amount as u64)?;
+        //the whole amount goes in, the fee stays in the input vault
+        self.swaptokens(amount_toswap, output_amount)?;
+
+        let input_vault = self.input_vault_account.key();
+        let lp_supply = self.lp_token_mint.supply;
+        let pool = &mut self.pool_stateaccount;
+        pool.credit_reserve(input_vault, amount_toswap)?;
+        pool.debit_reserve(self.output_vault_account.key(), output_amount)?;
+        self.pool_stateaccount.accrue_swap_fee(
+            input_vault,
+            amount_toswap - input_amount,
+            lp_supply,
+        )?;
+
+        emit!(SwapEvent {
+            pool: self.pool_stateaccount.key(),
+            signer: self.signer.key(),
+            recipient_account: self.user_output_account.key(),
+            recipient: self.user_output_account.owner,
+            input_mint: self.input_mint.key(),
+            output_mint: self.output_mint.key(),
+            amount_in: amount_toswap,
+            amount_out: output_amount,
+            fee: amount_toswap - input_amount,
+        });
         Ok(())
     }
 
@@ -379,17 +1350,7 @@ impl<'info> SwapTokens<'info> {
 
     //function to deduct fee
     pub fn deductfee(&self, amouunt_in: u64) -> u64 {
-        //constants for the fee
-        const FEE_NUMERATOR: u128 = 30;
-        const FEE_DENOMINATOR: u128 = 1000;
-
-        let amount_needed = amouunt_in as u128;
-
-        //calculate the fee
-        let fee = (amount_needed * FEE_NUMERATOR) / FEE_DENOMINATOR;
-
-        //return input_amount - fee
-        (amount_needed - fee) as u64
+        self.pool_stateaccount.amount_after_fee(amouunt_in)
     }
 
     pub fn swaptokens(&self, input_amount: u64, output_amount: u64) -> Result<()> {
@@ -399,27 +1360,12 @@ impl<'info> SwapTokens<'info> {
     }
 
     pub fn output_amount_calculation(&self, input_amount: u64) -> Result<u64> {
-        let input_vaultamount = self.input_vault_account.amount as u128;
-        let output_vaultamount = self.output_vault_account.amount as u128;
-
-        //product before swap
-        let product_before_swap = (input_vaultamount * output_vaultamount) as u128;
-
-        //formula to calculate amount
-        let outputamount = (output_vaultamount * input_amount as u128)
-            / (input_vaultamount + input_amount as u128);
-
-        //check if the product before and after is same
-        let input_vault_afterswap = input_vaultamount + input_amount as u128;
-        let output_vault_afterswap = output_vaultamount - outputamount as u128;
-
-        let product_after_swap = (input_vault_afterswap * output_vault_afterswap) as u128;
-
-        if product_after_swap < product_before_swap {
-            return err!(SwapTokenErrors::SwapError);
-        }
-
-        Ok(outputamount as u64)
+        //price on the pool curve
+        self.pool_stateaccount.swap_output(
+            input_amount,
+            &self.input_vault_account,
+            &self.output_vault_account,
+        )
     }
 
     //transfer input
@@ -441,8 +1387,6 @@ impl<'info> SwapTokens<'info> {
 
     //transfer output
     fn transferoutput(&self, amount_transfer: u64) -> Result<()> {
-        let usdc_mint = self.pool_stateaccount.usdc_mint;
-        let wsol_mint = self.pool_stateaccount.wsol_mint;
         let decimals = self.output_mint.decimals;
         //tranfer from user to input vault
         let cpi_accounts = TransferChecked {
@@ -452,17 +1396,11 @@ impl<'info> SwapTokens<'info> {
             authority: self.pool_stateaccount.to_account_info(),
         };
 
-        let seeds = [
-            b"pool_state_v3",
-            usdc_mint.as_ref(),
-            wsol_mint.as_ref(),
-            &[self.pool_stateaccount.bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
-
         let cpi_program = self.token_program.to_account_info();
-        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
-        token_interface::transfer_checked(cpi_context, amount_transfer, decimals)?;
+        self.pool_stateaccount.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount_transfer, decimals)
+        })?;
         Ok(())
     }
 }
@@ -474,45 +1412,70 @@ pub struct RemoveLiquidity<'info> {
     pub signer: Signer<'info>,
 
     //mint of usdc and wsol
+    #[account(address = pool_state_account.usdc_mint)]
     pub usdc_mint: InterfaceAccount<'info, Mint>,
+    #[account(address = pool_state_account.wsol_mint)]
     pub wsol_mint: InterfaceAccount<'info, Mint>,
 
-    //user accounts
-    #[account(mut,token::mint = pool_state_account.usdc_mint, token::authority = signer)]
+    //receive the withdrawal, any account of the pool mints
+    #[account(mut,token::mint = pool_state_account.usdc_mint)]
     pub user_usdc_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut,token::mint = pool_state_account.wsol_mint, token::authority = signer)]
+    #[account(mut,token::mint = pool_state_account.wsol_mint)]
     pub user_wsol_account: InterfaceAccount<'info, TokenAccount>,
 
     //vault accounts
-    #[account(mut)]
+    #[account(mut, address = pool_state_account.usdc_vault_address)]
     pub usdc_vault_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
+    #[account(mut, address = pool_state_account.wsol_vault_address)]
     pub wsol_vault_account: InterfaceAccount<'info, TokenAccount>,
 
     //token_program
     pub token_program: Interface<'info, TokenInterface>,
 
     //pool_state_account
-    #[account(mut)]
+    #[account(mut, constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked)]
     pub pool_state_account: Account<'info, LpPoolAccountShape>,
 
     //lp_token_mint
-    #[account(mut)]
+    #[account(mut, address = pool_state_account.lp_token_mint)]
     pub lp_mint: InterfaceAccount<'info, Mint>,
 
     //user lp token ata
-    #[account(mut)]
+    #[account(mut, token::mint = lp_mint, token::authority = signer)]
     pub user_lp_ata: InterfaceAccount<'info, TokenAccount>,
+
+    //claimable pools only, see checkpoint_before_lp_change
+    #[account(mut, seeds = [b"fee_checkpoint_v3", pool_state_account.key().as_ref(), signer.key().as_ref()], bump = fee_checkpoint.bump, constraint = fee_checkpoint.lp_account == user_lp_ata.key() @ FeeErrors::InvalidLpAccount)]
+    pub fee_checkpoint: Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+
+    //needed while the protocol fee is on, see mint_protocol_fee_lp
+    #[account(mut, address = pool_state_account.protocol_fee_account @ ProtocolFeeErrors::InvalidFeeAccount)]
+    pub protocol_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 }
 
 #[error_code]
 pub enum RemoveLiquidityErrors {
     #[msg("pool is empty")]
     EmptyPool,
+
+    #[msg("token is not part of the pool")]
+    InvalidToken,
+
+    #[msg("output amount is below the minimum")]
+    SlippageExceeded,
 }
 
 impl<'info> RemoveLiquidity<'info> {
-    fn remove_lp_main(&self, burnamount: u64) -> Result<()> {
+    fn remove_lp_main(&mut self, burnamount: u64) -> Result<()> {
+        mint_protocol_fee_lp(
+            &self.pool_state_account,
+            &mut self.lp_mint,
+            &self.protocol_fee_account,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            &self.token_program,
+        )?;
+
         //calcualte the amounts
         let (transferusdcamount, transfersolamount) = self.calculate_amount(burnamount)?;
 
@@ -521,30 +1484,124 @@ impl<'info> RemoveLiquidity<'info> {
 
         //call the tranfer function
         self.token_transfer(transferusdcamount, transfersolamount)?;
+        let (usdc_vault, wsol_vault) =
+            (self.usdc_vault_account.key(), self.wsol_vault_account.key());
+        self.pool_state_account
+            .debit_reserve(usdc_vault, transferusdcamount)?;
+        self.pool_state_account
+            .debit_reserve(wsol_vault, transfersolamount)?;
+        update_k_last(&mut self.pool_state_account);
+        Ok(())
+    }
+
+    fn remove_lp_single(
+        &mut self,
+        burnamount: u64,
+        output_token: Pubkey,
+        min_out: u64,
+    ) -> Result<()> {
+        let usdc_out = if output_token == self.pool_state_account.usdc_mint {
+            true
+        } else if output_token == self.pool_state_account.wsol_mint {
+            false
+        } else {
+            return err!(RemoveLiquidityErrors::InvalidToken);
+        };
+
+        mint_protocol_fee_lp(
+            &self.pool_state_account,
+            &mut self.lp_mint,
+            &self.protocol_fee_account,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            &self.token_program,
+        )?;
+
+        //pro rata amounts, same as the normal withdrawal
+        let (usdc_amount, wsol_amount) = self.calculate_amount(burnamount)?;
+
+        //reserves left after the withdrawal, the unwanted side is swapped against them
+        let usdc_left = self
+            .pool_state_account
+            .reserve(&self.usdc_vault_account)
+            .checked_sub(usdc_amount)
+            .ok_or(ReserveErrors::ReserveUnderflow)?;
+        let wsol_left = self
+            .pool_state_account
+            .reserve(&self.wsol_vault_account)
+            .checked_sub(wsol_amount)
+            .ok_or(ReserveErrors::ReserveUnderflow)?;
+        let (keep_amount, swap_amount, reserve_in, reserve_out, direction) = if usdc_out {
+            (
+                usdc_amount,
+                wsol_amount,
+                wsol_left,
+                usdc_left,
+                TradeDirection::BtoA,
+            )
+        } else {
+            (
+                wsol_amount,
+                usdc_amount,
+                usdc_left,
+                wsol_left,
+                TradeDirection::AtoB,
+            )
+        };
+
+        //same fee and curve as SwapTokens, the swapped tokens stay in the vault
+        let swap_net = self.pool_state_account.amount_after_fee(swap_amount);
+        let swap_out = if swap_net == 0 {
+            0
+        } else {
+            self.pool_state_account
+                .curve()
+                .swap(swap_net, reserve_in, reserve_out, direction)
+                .ok_or(SwapTokenErrors::SwapError)?
+        };
+
+        let total_out = keep_amount
+            .checked_add(swap_out)
+            .ok_or(ProvideLpErrors::MultiplicationError)?;
+        if total_out < min_out {
+            return err!(RemoveLiquidityErrors::SlippageExceeded);
+        }
+
+        self.burn_lptokens(burnamount)?;
+        if usdc_out {
+            self.transfer_usdc(total_out)?;
+        } else {
+            self.transfer_wsol(total_out)?;
+        }
+        let output_vault = if usdc_out {
+            self.usdc_vault_account.key()
+        } else {
+            self.wsol_vault_account.key()
+        };
+        self.pool_state_account
+            .debit_reserve(output_vault, total_out)?;
+        update_k_last(&mut self.pool_state_account);
         Ok(())
     }
 
     fn calculate_amount(&self, burnamount: u64) -> Result<(u64, u64)> {
         let total_supply = self.lp_mint.supply;
-        let usdc_vault_amount = self.usdc_vault_account.amount;
-        let wsol_vault_amount = self.wsol_vault_account.amount;
 
         //safety check for the token account
         if total_supply == 0 {
             return err!(RemoveLiquidityErrors::EmptyPool);
         }
 
-        let usdc_return_amount = (burnamount as u128)
-            .checked_mul(usdc_vault_amount as u128)
-            .unwrap()
-            .checked_div(total_supply as u128)
-            .unwrap() as u64;
-
-        let wsol_return_amount = (burnamount as u128)
-            .checked_mul(wsol_vault_amount as u128)
-            .unwrap()
-            .checked_div(total_supply as u128)
-            .unwrap() as u64;
+        let (usdc_return_amount, wsol_return_amount) = self
+            .pool_state_account
+            .curve()
+            .withdraw(
+                burnamount,
+                self.pool_state_account.reserve(&self.usdc_vault_account),
+                self.pool_state_account.reserve(&self.wsol_vault_account),
+                total_supply,
+            )
+            .ok_or(CurveErrors::CurveMathError)?;
 
         Ok((usdc_return_amount, wsol_return_amount))
     }
@@ -555,7 +1612,15 @@ impl<'info> RemoveLiquidity<'info> {
         Ok(())
     }
 
-    fn burn_lptokens(&self, burnamount: u64) -> Result<()> {
+    fn burn_lptokens(&mut self, burnamount: u64) -> Result<()> {
+        checkpoint_before_lp_change(
+            &mut self.pool_state_account,
+            &mut self.fee_checkpoint,
+            &self.lp_mint,
+            &self.user_lp_ata,
+            &self.token_program,
+        )?;
+
         let cpi_accounts = Burn {
             mint: self.lp_mint.to_account_info(),
             from: self.user_lp_ata.to_account_info(),
@@ -565,7 +1630,14 @@ impl<'info> RemoveLiquidity<'info> {
         let cpi_progam = self.token_program.to_account_info();
         let cpi_context = CpiContext::new(cpi_progam, cpi_accounts);
         token_interface::burn(cpi_context, burnamount)?;
-        Ok(())
+
+        checkpoint_after_lp_change(
+            &mut self.pool_state_account,
+            &mut self.fee_checkpoint,
+            &self.lp_mint,
+            &mut self.user_lp_ata,
+            &self.token_program,
+        )
     }
 
     //transfer usdc
@@ -579,20 +1651,11 @@ impl<'info> RemoveLiquidity<'info> {
             authority: self.pool_state_account.to_account_info(),
         };
 
-        let usdc_mint = self.pool_state_account.usdc_mint;
-        let wsol_mint = self.pool_state_account.wsol_mint;
-
-        let seeds = [
-            b"pool_state_v3",
-            usdc_mint.as_ref(),
-            wsol_mint.as_ref(),
-            &[self.pool_state_account.bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
-
         let cpi_program = self.token_program.to_account_info();
-        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
-        token_interface::transfer_checked(cpi_context, tranferusdcamount, decimals)?;
+        self.pool_state_account.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, tranferusdcamount, decimals)
+        })?;
         Ok(())
     }
 
@@ -608,20 +1671,3252 @@ impl<'info> RemoveLiquidity<'info> {
             authority: self.pool_state_account.to_account_info(),
         };
 
-        let usdc_mint = self.pool_state_account.usdc_mint;
-        let wsol_mint = self.pool_state_account.wsol_mint;
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_state_account.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, transfersolamount, decimals)
+        })?;
+        Ok(())
+    }
+}
 
-        let seeds = [
-            b"pool_state_v3",
+//---------- native sol ----------
+//the wsol side of the pool is fed with lamports and paid out through a
+//temporary wsol account that is closed back to the signer
+#[error_code]
+pub enum NativeSolErrors {
+    #[msg("pool wsol mint is not the native mint")]
+    NotNativeMint,
+}
+
+//wrap lamports straight into the wsol vault
+fn wrap_into_vault<'info>(
+    from: &Signer<'info>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    system_program: &Program<'info, System>,
+    token_program: &Interface<'info, TokenInterface>,
+    lamports: u64,
+) -> Result<()> {
+    let cpi_accounts = system_program::Transfer {
+        from: from.to_account_info(),
+        to: vault.to_account_info(),
+    };
+    let cpi_context = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+    system_program::transfer(cpi_context, lamports)?;
+
+    //update the token amount of the vault
+    let cpi_accounts = SyncNative {
+        account: vault.to_account_info(),
+    };
+    let cpi_context = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+    token_interface::sync_native(cpi_context)?;
+    Ok(())
+}
+
+//close the temporary wsol account, the signer gets the lamports back and refunds
+//the rent to the payer when someone else sponsored it
+fn unwrap_to_signer<'info>(
+    temp_account: &InterfaceAccount<'info, TokenAccount>,
+    signer: &Signer<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    //the account was deserialized before the output reached it, its amount is stale, the
+    //payer put in exactly the rent of the account size
+    let rent = Rent::get()?.minimum_balance(temp_account.to_account_info().data_len());
+    let cpi_accounts = CloseAccount {
+        account: temp_account.to_account_info(),
+        destination: signer.to_account_info(),
+        authority: signer.to_account_info(),
+    };
+    let cpi_context = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+    token_interface::close_account(cpi_context)?;
+
+    if payer.key() != signer.key() && rent > 0 {
+        let cpi_accounts = system_program::Transfer {
+            from: signer.to_account_info(),
+            to: payer.to_account_info(),
+        };
+        let cpi_context = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+        system_program::transfer(cpi_context, rent)?;
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ProvideLpNative<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    //pays the rent, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    //mints for the vaults
+    #[account(address = mint_authority.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = mint_authority.wsol_mint, constraint = wsol_mint.key() == native_mint::ID @ NativeSolErrors::NotNativeMint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //user usdc account, sol comes from the signer lamports
+    #[account(mut, token::mint = usdc_mint, token::authority = signer)]
+    pub user_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //vault accounts
+    #[account(mut, address = mint_authority.usdc_vault_address)]
+    pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = mint_authority.wsol_vault_address)]
+    pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //lp mint and the user lp account
+    #[account(mut, address = mint_authority.lp_token_mint)]
+    pub lptokenmint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(init_if_needed, payer = payer, associated_token::mint = lptokenmint, associated_token::authority = signer, associated_token::token_program = token_program)]
+    pub lp_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    //pool state, authority of the lp mint
+    #[account(mut, constraint = !mint_authority.locked @ FlashLoanErrors::PoolLocked)]
+    pub mint_authority: Box<Account<'info, LpPoolAccountShape>>,
+
+    //claimable pools only, see checkpoint_before_lp_change
+    #[account(mut, seeds = [b"fee_checkpoint_v3", mint_authority.key().as_ref(), signer.key().as_ref()], bump = fee_checkpoint.bump, constraint = fee_checkpoint.lp_account == lp_ata.key() @ FeeErrors::InvalidLpAccount)]
+    pub fee_checkpoint: Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+
+    //needed while the protocol fee is on, see mint_protocol_fee_lp
+    #[account(mut, address = mint_authority.protocol_fee_account @ ProtocolFeeErrors::InvalidFeeAccount)]
+    pub protocol_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    //receives MINIMUM_LIQUIDITY on the first deposit
+    #[account(mut, seeds = [b"locked_lp_v3", mint_authority.key().as_ref()], bump)]
+    pub locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ProvideLpNative<'info> {
+    fn token_transfer(&mut self, lamports: u64, usdc_amount: u64) -> Result<()> {
+        mint_protocol_fee_lp(
+            &self.mint_authority,
+            &mut self.lptokenmint,
+            &self.protocol_fee_account,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            &self.token_program,
+        )?;
+
+        //calculate the lp token amount need to provide
+        let lp_amount = self.mint_authority.deposit_lp_at_reserves(
+            usdc_amount,
+            lamports,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            self.lptokenmint.supply,
+        )?;
+
+        //usdc from the user account, sol from the signer lamports
+        self.tranfer_usdc(usdc_amount)?;
+        wrap_into_vault(
+            &self.signer,
+            &self.wsol_vault_account,
+            &self.system_program,
+            &self.token_program,
+            lamports,
+        )?;
+
+        lock_minimum_liquidity(
+            &self.mint_authority,
+            &mut self.lptokenmint,
+            &self.locked_lp_account,
+            &self.token_program,
+        )?;
+        self.mint_lptokens(lp_amount)?;
+        let (usdc_vault, wsol_vault) =
+            (self.usdc_vault_account.key(), self.wsol_vault_account.key());
+        self.mint_authority
+            .credit_reserve(usdc_vault, usdc_amount)?;
+        self.mint_authority.credit_reserve(wsol_vault, lamports)?;
+        update_k_last(&mut self.mint_authority);
+        Ok(())
+    }
+
+    fn tranfer_usdc(&self, amount: u64) -> Result<()> {
+        let decimals = self.usdc_mint.decimals;
+        let cpi_accounts = TransferChecked {
+            mint: self.usdc_mint.to_account_info(),
+            from: self.user_usdc_account.to_account_info(),
+            to: self.usdc_vault_account.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_context, amount, decimals)?;
+        Ok(())
+    }
+
+    fn mint_lptokens(&mut self, amount: u64) -> Result<()> {
+        checkpoint_before_lp_change(
+            &mut self.mint_authority,
+            &mut self.fee_checkpoint,
+            &self.lptokenmint,
+            &self.lp_ata,
+            &self.token_program,
+        )?;
+
+        let cpi_accounts = MintTo {
+            mint: self.lptokenmint.to_account_info(),
+            to: self.lp_ata.to_account_info(),
+            authority: self.mint_authority.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.mint_authority.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::mint_to(cpi_context, amount)
+        })?;
+
+        checkpoint_after_lp_change(
+            &mut self.mint_authority,
+            &mut self.fee_checkpoint,
+            &self.lptokenmint,
+            &mut self.lp_ata,
+            &self.token_program,
+        )
+    }
+}
+
+//sol in, usdc out
+#[derive(Accounts)]
+pub struct SwapSolToToken<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(address = pool_stateaccount.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //user receives usdc here
+    #[account(mut, token::mint = usdc_mint, token::authority = signer)]
+    pub user_output_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //vaults for the transaction
+    #[account(mut, address = pool_stateaccount.wsol_vault_address, constraint = input_vault_account.mint == native_mint::ID @ NativeSolErrors::NotNativeMint)]
+    pub input_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool_stateaccount.usdc_vault_address)]
+    pub output_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, has_one = lp_token_mint, constraint = !pool_stateaccount.locked @ FlashLoanErrors::PoolLocked)]
+    pub pool_stateaccount: Box<Account<'info, LpPoolAccountShape>>,
+
+    //lp supply for the claimable fee accounting
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SwapSolToToken<'info> {
+    pub fn main_swap_function(&mut self, lamports: u64) -> Result<()> {
+        if self.signer.lamports() < lamports {
+            return err!(SwapTokenErrors::AmountError);
+        }
+
+        let input_amount = self.pool_stateaccount.amount_after_fee(lamports);
+        let output_amount = self.pool_stateaccount.swap_output(
+            input_amount,
+            &self.input_vault_account,
+            &self.output_vault_account,
+        )?;
+
+        wrap_into_vault(
+            &self.signer,
+            &self.input_vault_account,
+            &self.system_program,
+            &self.token_program,
+            lamports,
+        )?;
+        self.transferoutput(output_amount)?;
+
+        let input_vault = self.input_vault_account.key();
+        let lp_supply = self.lp_token_mint.supply;
+        let pool = &mut self.pool_stateaccount;
+        pool.credit_reserve(input_vault, lamports)?;
+        pool.debit_reserve(self.output_vault_account.key(), output_amount)?;
+        pool.accrue_swap_fee(input_vault, lamports - input_amount, lp_supply)?;
+        Ok(())
+    }
+
+    fn transferoutput(&self, amount_transfer: u64) -> Result<()> {
+        let decimals = self.usdc_mint.decimals;
+        let cpi_accounts = TransferChecked {
+            mint: self.usdc_mint.to_account_info(),
+            from: self.output_vault_account.to_account_info(),
+            to: self.user_output_account.to_account_info(),
+            authority: self.pool_stateaccount.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_stateaccount.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount_transfer, decimals)
+        })?;
+        Ok(())
+    }
+}
+
+//usdc in, sol out
+#[derive(Accounts)]
+pub struct SwapTokenToSol<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(address = pool_stateaccount.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool_stateaccount.wsol_mint, constraint = wsol_mint.key() == native_mint::ID @ NativeSolErrors::NotNativeMint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //user pays usdc from here
+    #[account(mut, token::mint = usdc_mint, token::authority = signer)]
+    pub user_input_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //vaults for the transaction
+    #[account(mut, address = pool_stateaccount.usdc_vault_address)]
+    pub input_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool_stateaccount.wsol_vault_address)]
+    pub output_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //temporary wsol account, closed to the signer at the end, the rent goes back to the payer
+    #[account(init, payer = payer, token::mint = wsol_mint, token::authority = signer, token::token_program = token_program, seeds = [b"temp_wsol_v3", signer.key().as_ref()], bump)]
+    pub temp_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, has_one = lp_token_mint, constraint = !pool_stateaccount.locked @ FlashLoanErrors::PoolLocked)]
+    pub pool_stateaccount: Box<Account<'info, LpPoolAccountShape>>,
+
+    //lp supply for the claimable fee accounting
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+
+    //pays the rent, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+impl<'info> SwapTokenToSol<'info> {
+    pub fn main_swap_function(&mut self, amount_toswap: u64) -> Result<()> {
+        if self.user_input_account.amount < amount_toswap {
+            return err!(SwapTokenErrors::AmountError);
+        }
+
+        let input_amount = self.pool_stateaccount.amount_after_fee(amount_toswap);
+        let output_amount = self.pool_stateaccount.swap_output(
+            input_amount,
+            &self.input_vault_account,
+            &self.output_vault_account,
+        )?;
+
+        self.transferinput(amount_toswap)?;
+        self.transferoutput(output_amount)?;
+        unwrap_to_signer(
+            &self.temp_wsol_account,
+            &self.signer,
+            &self.payer,
+            &self.system_program,
+            &self.token_program,
+        )?;
+
+        let input_vault = self.input_vault_account.key();
+        let lp_supply = self.lp_token_mint.supply;
+        let pool = &mut self.pool_stateaccount;
+        pool.credit_reserve(input_vault, amount_toswap)?;
+        pool.debit_reserve(self.output_vault_account.key(), output_amount)?;
+        self.pool_stateaccount.accrue_swap_fee(
+            input_vault,
+            amount_toswap - input_amount,
+            lp_supply,
+        )?;
+        Ok(())
+    }
+
+    fn transferinput(&self, amount_toswap: u64) -> Result<()> {
+        let decimals = self.usdc_mint.decimals;
+        let cpi_accounts = TransferChecked {
+            mint: self.usdc_mint.to_account_info(),
+            from: self.user_input_account.to_account_info(),
+            to: self.input_vault_account.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_context, amount_toswap, decimals)?;
+        Ok(())
+    }
+
+    //pay out into the temporary wsol account
+    fn transferoutput(&self, amount_transfer: u64) -> Result<()> {
+        let decimals = self.wsol_mint.decimals;
+        let cpi_accounts = TransferChecked {
+            mint: self.wsol_mint.to_account_info(),
+            from: self.output_vault_account.to_account_info(),
+            to: self.temp_wsol_account.to_account_info(),
+            authority: self.pool_stateaccount.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_stateaccount.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount_transfer, decimals)
+        })?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidityNative<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(address = pool_state_account.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool_state_account.wsol_mint, constraint = wsol_mint.key() == native_mint::ID @ NativeSolErrors::NotNativeMint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //user usdc account, sol is paid out as lamports
+    #[account(mut, token::mint = usdc_mint, token::authority = signer)]
+    pub user_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //vault accounts
+    #[account(mut, address = pool_state_account.usdc_vault_address)]
+    pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool_state_account.wsol_vault_address)]
+    pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //temporary wsol account, closed to the signer at the end, the rent goes back to the payer
+    #[account(init, payer = payer, token::mint = wsol_mint, token::authority = signer, token::token_program = token_program, seeds = [b"temp_wsol_v3", signer.key().as_ref()], bump)]
+    pub temp_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    //lp mint and the user lp account
+    #[account(mut, address = pool_state_account.lp_token_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, token::mint = lp_mint, token::authority = signer)]
+    pub user_lp_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //claimable pools only, see checkpoint_before_lp_change
+    #[account(mut, seeds = [b"fee_checkpoint_v3", pool_state_account.key().as_ref(), signer.key().as_ref()], bump = fee_checkpoint.bump, constraint = fee_checkpoint.lp_account == user_lp_ata.key() @ FeeErrors::InvalidLpAccount)]
+    pub fee_checkpoint: Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+
+    //needed while the protocol fee is on, see mint_protocol_fee_lp
+    #[account(mut, address = pool_state_account.protocol_fee_account @ ProtocolFeeErrors::InvalidFeeAccount)]
+    pub protocol_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+
+    //pays the rent, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+impl<'info> RemoveLiquidityNative<'info> {
+    fn remove_lp_main(&mut self, burnamount: u64) -> Result<()> {
+        mint_protocol_fee_lp(
+            &self.pool_state_account,
+            &mut self.lp_mint,
+            &self.protocol_fee_account,
+            &self.usdc_vault_account,
+            &self.wsol_vault_account,
+            &self.token_program,
+        )?;
+
+        let total_supply = self.lp_mint.supply;
+        if total_supply == 0 {
+            return err!(RemoveLiquidityErrors::EmptyPool);
+        }
+
+        let (usdc_return_amount, wsol_return_amount) = self
+            .pool_state_account
+            .curve()
+            .withdraw(
+                burnamount,
+                self.pool_state_account.reserve(&self.usdc_vault_account),
+                self.pool_state_account.reserve(&self.wsol_vault_account),
+                total_supply,
+            )
+            .ok_or(CurveErrors::CurveMathError)?;
+
+        self.burn_lptokens(burnamount)?;
+        self.transfer_from_vault(
+            &self.usdc_mint,
+            &self.usdc_vault_account,
+            &self.user_usdc_account,
+            usdc_return_amount,
+        )?;
+        self.transfer_from_vault(
+            &self.wsol_mint,
+            &self.wsol_vault_account,
+            &self.temp_wsol_account,
+            wsol_return_amount,
+        )?;
+        unwrap_to_signer(
+            &self.temp_wsol_account,
+            &self.signer,
+            &self.payer,
+            &self.system_program,
+            &self.token_program,
+        )?;
+        let (usdc_vault, wsol_vault) =
+            (self.usdc_vault_account.key(), self.wsol_vault_account.key());
+        self.pool_state_account
+            .debit_reserve(usdc_vault, usdc_return_amount)?;
+        self.pool_state_account
+            .debit_reserve(wsol_vault, wsol_return_amount)?;
+        update_k_last(&mut self.pool_state_account);
+        Ok(())
+    }
+
+    fn burn_lptokens(&mut self, burnamount: u64) -> Result<()> {
+        checkpoint_before_lp_change(
+            &mut self.pool_state_account,
+            &mut self.fee_checkpoint,
+            &self.lp_mint,
+            &self.user_lp_ata,
+            &self.token_program,
+        )?;
+
+        let cpi_accounts = Burn {
+            mint: self.lp_mint.to_account_info(),
+            from: self.user_lp_ata.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_progam = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_progam, cpi_accounts);
+        token_interface::burn(cpi_context, burnamount)?;
+
+        checkpoint_after_lp_change(
+            &mut self.pool_state_account,
+            &mut self.fee_checkpoint,
+            &self.lp_mint,
+            &mut self.user_lp_ata,
+            &self.token_program,
+        )
+    }
+
+    fn transfer_from_vault(
+        &self,
+        mint: &InterfaceAccount<'info, Mint>,
+        vault: &InterfaceAccount<'info, TokenAccount>,
+        to: &InterfaceAccount<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: mint.to_account_info(),
+            from: vault.to_account_info(),
+            to: to.to_account_info(),
+            authority: self.pool_state_account.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_state_account.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount, mint.decimals)
+        })?;
+        Ok(())
+    }
+}
+
+//---------- flash loans ----------
+#[error_code]
+pub enum FlashLoanErrors {
+    #[msg("pool is locked by an open flash loan")]
+    PoolLocked,
+
+    #[msg("no flash loan is open on this pool")]
+    NoOpenLoan,
+
+    #[msg("flash loan must be followed by flash_repay in the same transaction")]
+    MissingRepay,
+
+    #[msg("flash loan can not be called through cpi")]
+    CpiNotAllowed,
+
+    #[msg("vault does not belong to the pool")]
+    InvalidVault,
+
+    #[msg("loan amount is more then the vault balance")]
+    AmountError,
+}
+
+//fee on the borrowed amount, 9 bps
+const FLASH_LOAN_FEE_NUMERATOR: u128 = 9;
+const FLASH_LOAN_FEE_DENOMINATOR: u128 = 10000;
+
+//position of the pool state in the FlashRepay accounts
+const FLASH_REPAY_POOL_INDEX: usize = 1;
+
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut, constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    //mint of the borrowed token
+    #[account(address = vault_account.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //vault to lend from, either of the pool vaults
+    #[account(mut, constraint = vault_account.key() == pool_state_account.usdc_vault_address || vault_account.key() == pool_state_account.wsol_vault_address @ FlashLoanErrors::InvalidVault)]
+    pub vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //borrower receives the tokens here
+    #[account(mut, token::mint = mint)]
+    pub borrower_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: instructions sysvar, checked by address
+    #[account(address = solana_instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> FlashLoan<'info> {
+    fn flash_loan_main(&mut self, amount: u64) -> Result<()> {
+        if amount > self.pool_state_account.reserve(&self.vault_account) {
+            return err!(FlashLoanErrors::AmountError);
+        }
+
+        //look for the repay before lending anything
+        self.check_repay()?;
+
+        //lock the pool until the repay
+        let pool = &mut self.pool_state_account;
+        pool.locked = true;
+        pool.flash_loan_vault = self.vault_account.key();
+        pool.flash_loan_amount = amount;
+
+        self.transfer_loan(amount)?;
+        Ok(())
+    }
+
+    fn check_repay(&self) -> Result<()> {
+        let instructions = self.instructions.to_account_info();
+        let current_index = load_current_index_checked(&instructions)? as usize;
+
+        //the loan has to be a top level instruction of this program
+        let current = load_instruction_at_checked(current_index, &instructions)?;
+        if current.program_id != crate::ID {
+            return err!(FlashLoanErrors::CpiNotAllowed);
+        }
+
+        //scan the following instructions for a matching repay
+        let mut index = current_index + 1;
+        while let Ok(ix) = load_instruction_at_checked(index, &instructions) {
+            if ix.program_id == crate::ID
+                && ix.data.starts_with(instruction::FlashRepay::DISCRIMINATOR)
+                && ix
+                    .accounts
+                    .get(FLASH_REPAY_POOL_INDEX)
+                    .is_some_and(|meta| meta.pubkey == self.pool_state_account.key())
+            {
+                return Ok(());
+            }
+            index += 1;
+        }
+
+        err!(FlashLoanErrors::MissingRepay)
+    }
+
+    fn transfer_loan(&self, amount: u64) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: self.mint.to_account_info(),
+            from: self.vault_account.to_account_info(),
+            to: self.borrower_account.to_account_info(),
+            authority: self.pool_state_account.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_state_account.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount, self.mint.decimals)
+        })?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    pub signer: Signer<'info>,
+
+    //keep at FLASH_REPAY_POOL_INDEX, flash_loan looks for it there
+    #[account(mut, constraint = pool_state_account.locked @ FlashLoanErrors::NoOpenLoan)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    #[account(address = vault_account.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //vault the loan came from
+    #[account(mut, address = pool_state_account.flash_loan_vault @ FlashLoanErrors::InvalidVault)]
+    pub vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //repayment is taken from here
+    #[account(mut, token::mint = mint, token::authority = signer)]
+    pub repayer_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> FlashRepay<'info> {
+    fn flash_repay_main(&mut self) -> Result<()> {
+        let amount = self.pool_state_account.flash_loan_amount;
+
+        //fee is rounded up in favour of the pool
+        let fee = ((amount as u128) * FLASH_LOAN_FEE_NUMERATOR).div_ceil(FLASH_LOAN_FEE_DENOMINATOR)
+            as u64;
+        let repay_amount = amount
+            .checked_add(fee)
+            .ok_or(ProvideLpErrors::MultiplicationError)?;
+
+        let cpi_accounts = TransferChecked {
+            mint: self.mint.to_account_info(),
+            from: self.repayer_account.to_account_info(),
+            to: self.vault_account.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_context, repay_amount, self.mint.decimals)?;
+
+        //the loan never left the reserve, the fee compounds into it
+        let vault = self.vault_account.key();
+        let pool = &mut self.pool_state_account;
+        pool.credit_reserve(vault, fee)?;
+
+        //unlock the pool
+        pool.locked = false;
+        pool.flash_loan_vault = Pubkey::default();
+        pool.flash_loan_amount = 0;
+        Ok(())
+    }
+}
+
+//---------- flash swaps ----------
+#[error_code]
+pub enum FlashSwapErrors {
+    #[msg("callback program is not allowed")]
+    InvalidCallbackProgram,
+
+    #[msg("nothing was paid back to the pool")]
+    NothingRepaid,
+
+    #[msg("constant product invariant does not hold after the callback")]
+    InvariantError,
+}
+
+//sha256("global:flash_swap_callback")[..8], the callback program exposes this instruction
+pub const FLASH_SWAP_CALLBACK_DISCRIMINATOR: [u8; 8] = [225, 54, 80, 1, 45, 208, 202, 124];
+
+//data handed to the callback after the discriminator, the fee tier and the stored
+//reserves before the swap are what check_invariant prices the repayment against
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FlashSwapCallbackArgs {
+    pub amount_out: u64,
+    pub fee_tier_bps: u16,
+    pub input_reserve: u64,
+    pub output_reserve: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Accounts)]
+pub struct FlashSwap<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut, constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked, constraint = pool_state_account.curve_type == CurveType::ConstantProduct @ CurveErrors::UnsupportedCurve)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    //mint of the token sent out first
+    #[account(address = output_vault_account.mint)]
+    pub output_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //vaults for the swap, checked against the pool in flash_swap_main
+    #[account(mut)]
+    pub input_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub output_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //receives the output before the callback
+    #[account(mut, token::mint = output_mint)]
+    pub recipient_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: any executable program, it is only invoked
+    #[account(executable)]
+    pub callback_program: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> FlashSwap<'info> {
+    fn flash_swap_main(
+        &mut self,
+        amount_out: u64,
+        data: Vec<u8>,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        self.checks(amount_out)?;
+
+        //reserves and balances before anything moves
+        let input_before = self.pool_state_account.reserve(&self.input_vault_account);
+        let output_before = self.pool_state_account.reserve(&self.output_vault_account);
+        let input_balance = self.input_vault_account.amount;
+        let output_balance = self.output_vault_account.amount.saturating_sub(amount_out);
+
+        //lock the pool and persist it so the callback can not re-enter
+        self.pool_state_account.locked = true;
+        self.pool_state_account.exit(&crate::ID)?;
+
+        self.transferoutput(amount_out)?;
+        let args = FlashSwapCallbackArgs {
+            amount_out,
+            fee_tier_bps: self.pool_state_account.fee_tier_bps,
+            input_reserve: input_before,
+            output_reserve: output_before,
+            data,
+        };
+        self.invoke_callback(args, remaining_accounts)?;
+
+        //balances after the callback paid back, only the growth counts as paid
+        self.input_vault_account.reload()?;
+        self.output_vault_account.reload()?;
+        let input_paid = self
+            .input_vault_account
+            .amount
+            .saturating_sub(input_balance);
+        let output_paid = self
+            .output_vault_account
+            .amount
+            .saturating_sub(output_balance);
+        let input_after = input_before
+            .checked_add(input_paid)
+            .ok_or(ProvideLpErrors::MultiplicationError)?;
+        let output_after = (output_before - amount_out)
+            .checked_add(output_paid)
+            .ok_or(ProvideLpErrors::MultiplicationError)?;
+        Self::check_invariant(
+            (input_before, output_before),
+            (input_after, output_after),
+            (input_paid, output_paid),
+            self.pool_state_account.fee_tier_bps,
+        )?;
+
+        let (input_vault, output_vault) = (
+            self.input_vault_account.key(),
+            self.output_vault_account.key(),
+        );
+        let pool = &mut self.pool_state_account;
+        pool.credit_reserve(input_vault, input_paid)?;
+        pool.debit_reserve(output_vault, amount_out)?;
+        pool.credit_reserve(output_vault, output_paid)?;
+        pool.locked = false;
+        Ok(())
+    }
+
+    fn checks(&self, amount_out: u64) -> Result<()> {
+        let pool = &self.pool_state_account;
+        let input_vault = self.input_vault_account.key();
+        let output_vault = self.output_vault_account.key();
+
+        let pair_ok = (input_vault == pool.usdc_vault_address
+            && output_vault == pool.wsol_vault_address)
+            || (input_vault == pool.wsol_vault_address && output_vault == pool.usdc_vault_address);
+        if !pair_ok {
+            return err!(SwapTokenErrors::InputVaultError);
+        }
+
+        //the vault can not be emptied
+        if amount_out == 0
+            || amount_out >= self.pool_state_account.reserve(&self.output_vault_account)
+        {
+            return err!(SwapTokenErrors::AmountError);
+        }
+
+        //no calling back into the pool or the token program
+        let callback = self.callback_program.key();
+        if callback == crate::ID || callback == self.token_program.key() {
+            return err!(FlashSwapErrors::InvalidCallbackProgram);
+        }
+        Ok(())
+    }
+
+    fn invoke_callback(
+        &self,
+        args: FlashSwapCallbackArgs,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let mut ix_data = FLASH_SWAP_CALLBACK_DISCRIMINATOR.to_vec();
+        args.serialize(&mut ix_data)?;
+
+        //callback gets the remaining accounts as they were passed in
+        let accounts = remaining_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect();
+
+        let ix = Instruction {
+            program_id: self.callback_program.key(),
+            accounts,
+            data: ix_data,
+        };
+        invoke(&ix, remaining_accounts)?;
+        Ok(())
+    }
+
+    //fee adjusted constant product, same fee as deductfee, charged on what came back
+    //reserves before, reserves after and what came back, each as (input, output)
+    fn check_invariant(
+        (input_before, output_before): (u64, u64),
+        (input_after, output_after): (u64, u64),
+        (input_paid, output_paid): (u64, u64),
+        fee_bps: u16,
+    ) -> Result<()> {
+        use curve::FEE_DENOMINATOR;
+        let fee_numerator = fee_bps as u128;
+
+        let (input_after, output_after) = (input_after as u128, output_after as u128);
+        let (input_paid, output_paid) = (input_paid as u128, output_paid as u128);
+        if input_paid == 0 && output_paid == 0 {
+            return err!(FlashSwapErrors::NothingRepaid);
+        }
+
+        let input_adjusted = input_after - (input_paid * fee_numerator).div_ceil(FEE_DENOMINATOR);
+        let output_adjusted =
+            output_after - (output_paid * fee_numerator).div_ceil(FEE_DENOMINATOR);
+
+        let product_before = input_before as u128 * output_before as u128;
+        let product_after = input_adjusted * output_adjusted;
+        if product_after < product_before {
+            return err!(FlashSwapErrors::InvariantError);
+        }
+        Ok(())
+    }
+
+    fn transferoutput(&self, amount_transfer: u64) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: self.output_mint.to_account_info(),
+            from: self.output_vault_account.to_account_info(),
+            to: self.recipient_account.to_account_info(),
+            authority: self.pool_state_account.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_state_account.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(
+                cpi_context,
+                amount_transfer,
+                self.output_mint.decimals,
+            )
+        })?;
+        Ok(())
+    }
+}
+
+//---------- routed swaps ----------
+#[error_code]
+pub enum RouteSwapErrors {
+    #[msg("route needs between one and MAX_ROUTE_HOPS hops")]
+    InvalidHopCount,
+
+    #[msg("hop vaults do not belong to the hop pool")]
+    VaultMismatch,
+
+    #[msg("hop input does not match the previous output")]
+    MintMismatch,
+
+    #[msg("output amount is below the minimum")]
+    SlippageExceeded,
+}
+
+//accounts per hop: pool state, input vault, output vault, output mint
+const ROUTE_HOP_ACCOUNTS: usize = 4;
+const MAX_ROUTE_HOPS: usize = 4;
+
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    pub signer: Signer<'info>,
+
+    //mints at both ends of the route
+    pub input_mint: InterfaceAccount<'info, Mint>,
+    pub output_mint: InterfaceAccount<'info, Mint>,
+
+    //user accounts at both ends of the route
+    #[account(mut, token::mint = input_mint, token::authority = signer)]
+    pub user_input_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = output_mint, token::authority = signer)]
+    pub user_output_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+//one pool of the route
+struct RouteHop<'info> {
+    pool: Account<'info, LpPoolAccountShape>,
+    input_vault: InterfaceAccount<'info, TokenAccount>,
+    output_vault: InterfaceAccount<'info, TokenAccount>,
+    output_mint: InterfaceAccount<'info, Mint>,
+}
+
+impl<'info> RouteSwap<'info> {
+    fn route_swap_main(
+        &self,
+        amount_in: u64,
+        min_amount_out: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        if amount_in == 0 || self.user_input_account.amount < amount_in {
+            return err!(SwapTokenErrors::AmountError);
+        }
+
+        let hop_count = remaining_accounts.len() / ROUTE_HOP_ACCOUNTS;
+        if remaining_accounts.len() % ROUTE_HOP_ACCOUNTS != 0
+            || hop_count == 0
+            || hop_count > MAX_ROUTE_HOPS
+        {
+            return err!(RouteSwapErrors::InvalidHopCount);
+        }
+
+        let mut amount = amount_in;
+        let mut input_mint = self.input_mint.key();
+        for index in 0..hop_count {
+            let mut hop = Self::load_hop(
+                &remaining_accounts[index * ROUTE_HOP_ACCOUNTS..(index + 1) * ROUTE_HOP_ACCOUNTS],
+                input_mint,
+            )?;
+
+            //the first hop is funded by the user
+            if index == 0 {
+                self.transferinput(&hop.input_vault, amount)?;
+            }
+
+            let output_amount =
+                Self::hop_output_amount(&hop, hop.pool.reserve(&hop.input_vault), amount)?;
+
+            //intermediate amounts go vault to vault, the last one to the user
+            if index + 1 == hop_count {
+                if hop.output_mint.key() != self.output_mint.key() {
+                    return err!(RouteSwapErrors::MintMismatch);
+                }
+                self.transferoutput(
+                    &hop,
+                    &self.user_output_account.to_account_info(),
+                    output_amount,
+                )?;
+            } else {
+                let next_input_vault = &remaining_accounts[(index + 1) * ROUTE_HOP_ACCOUNTS + 1];
+                self.transferoutput(&hop, next_input_vault, output_amount)?;
+            }
+
+            //hop pools are loaded by hand, persist the reserves before the next hop loads
+            let (input_vault, output_vault) = (hop.input_vault.key(), hop.output_vault.key());
+            hop.pool.credit_reserve(input_vault, amount)?;
+            hop.pool.debit_reserve(output_vault, output_amount)?;
+            hop.pool.exit(&crate::ID)?;
+
+            amount = output_amount;
+            input_mint = hop.output_mint.key();
+        }
+
+        if amount < min_amount_out {
+            return err!(RouteSwapErrors::SlippageExceeded);
+        }
+        Ok(())
+    }
+
+    fn load_hop(
+        accounts: &'info [AccountInfo<'info>],
+        input_mint: Pubkey,
+    ) -> Result<RouteHop<'info>> {
+        let pool = Account::<LpPoolAccountShape>::try_from(&accounts[0])?;
+        let input_vault = InterfaceAccount::<TokenAccount>::try_from(&accounts[1])?;
+        let output_vault = InterfaceAccount::<TokenAccount>::try_from(&accounts[2])?;
+        let output_mint = InterfaceAccount::<Mint>::try_from(&accounts[3])?;
+
+        if pool.locked {
+            return err!(FlashLoanErrors::PoolLocked);
+        }
+
+        //vaults have to be the two vaults of this pool
+        let pair_ok = (input_vault.key() == pool.usdc_vault_address
+            && output_vault.key() == pool.wsol_vault_address)
+            || (input_vault.key() == pool.wsol_vault_address
+                && output_vault.key() == pool.usdc_vault_address);
+        if !pair_ok {
+            return err!(RouteSwapErrors::VaultMismatch);
+        }
+
+        //hops have to chain
+        if input_vault.mint != input_mint || output_vault.mint != output_mint.key() {
+            return err!(RouteSwapErrors::MintMismatch);
+        }
+
+        Ok(RouteHop {
+            pool,
+            input_vault,
+            output_vault,
+            output_mint,
+        })
+    }
+
+    //same fee and curve as SwapTokens, the fee stays in the input vault
+    fn hop_output_amount(hop: &RouteHop<'info>, reserve_in: u64, amount_in: u64) -> Result<u64> {
+        let outputamount = hop
+            .pool
+            .curve()
+            .swap(
+                hop.pool.amount_after_fee(amount_in),
+                reserve_in,
+                hop.pool.reserve(&hop.output_vault),
+                hop.pool.direction(hop.input_vault.key()),
+            )
+            .ok_or(SwapTokenErrors::SwapError)?;
+
+        if outputamount == 0 {
+            return err!(SwapTokenErrors::SwapError);
+        }
+        Ok(outputamount)
+    }
+
+    fn transferinput(
+        &self,
+        vault: &InterfaceAccount<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: self.input_mint.to_account_info(),
+            from: self.user_input_account.to_account_info(),
+            to: vault.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_context, amount, self.input_mint.decimals)?;
+        Ok(())
+    }
+
+    fn transferoutput(
+        &self,
+        hop: &RouteHop<'info>,
+        to: &AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: hop.output_mint.to_account_info(),
+            from: hop.output_vault.to_account_info(),
+            to: to.clone(),
+            authority: hop.pool.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        hop.pool.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount, hop.output_mint.decimals)
+        })?;
+        Ok(())
+    }
+}
+
+//---------- config and registry ----------
+#[error_code]
+pub enum ConfigErrors {
+    #[msg("signer is not allowed to change the config")]
+    Unauthorized,
+
+    #[msg("fee tiers have to be unique, increasing and at most MAX_FEE_TIER_BPS")]
+    InvalidFeeTiers,
+
+    #[msg("curve is not allowed by the config")]
+    CurveNotAllowed,
+
+    #[msg("fee tier is not one of the config fee tiers")]
+    FeeTierNotAllowed,
+
+    #[msg("account is not the fee receiver of the config")]
+    InvalidFeeReceiver,
+}
+
+pub const MAX_FEE_TIERS: usize = 8;
+pub const MAX_FEE_TIER_BPS: u16 = 1_000;
+pub const POOLS_PER_REGISTRY_PAGE: u64 = 64;
+//allowed_curves bit of concentrated liquidity pools, the bits below it are 1 << CurveType
+pub const CLMM_CURVE_BIT: u8 = 1 << 5;
+
+#[account]
+#[derive(InitSpace)]
+pub struct AmmConfigAccountShape {
+    pub admin: Pubkey,
+    //receives the pool creation fee
+    pub fee_receiver: Pubkey,
+    //lamports paid by the pool creator
+    pub pool_creation_fee: u64,
+    //one bit per CurveType, 1 << curve_type, and CLMM_CURVE_BIT
+    pub allowed_curves: u8,
+    //swap fee tiers in basis points
+    #[max_len(MAX_FEE_TIERS)]
+    pub fee_tiers: Vec<u16>,
+    //pools registered so far, the next one goes to page pool_count / POOLS_PER_REGISTRY_PAGE
+    pub pool_count: u64,
+    pub bump: u8,
+}
+
+//settings for initialize_config and update_config
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AmmConfigParams {
+    pub admin: Pubkey,
+    pub fee_receiver: Pubkey,
+    pub pool_creation_fee: u64,
+    pub allowed_curves: u8,
+    pub fee_tiers: Vec<u16>,
+}
+
+impl AmmConfigAccountShape {
+    pub fn allows_curve(&self, curve_bit: u8) -> bool {
+        self.allowed_curves & curve_bit != 0
+    }
+
+    fn apply(&mut self, params: AmmConfigParams) -> Result<()> {
+        let tiers_ok = !params.fee_tiers.is_empty()
+            && params.fee_tiers.len() <= MAX_FEE_TIERS
+            && params.fee_tiers.windows(2).all(|pair| pair[0] < pair[1])
+            && params
+                .fee_tiers
+                .iter()
+                .all(|tier| *tier <= MAX_FEE_TIER_BPS);
+        if !tiers_ok {
+            return err!(ConfigErrors::InvalidFeeTiers);
+        }
+
+        self.admin = params.admin;
+        self.fee_receiver = params.fee_receiver;
+        self.pool_creation_fee = params.pool_creation_fee;
+        self.allowed_curves = params.allowed_curves;
+        self.fee_tiers = params.fee_tiers;
+        Ok(())
+    }
+}
+
+//one page of the pool registry, pages fill up in order
+#[account]
+#[derive(InitSpace)]
+pub struct PoolRegistryPageAccountShape {
+    pub page_index: u64,
+    #[max_len(POOLS_PER_REGISTRY_PAGE)]
+    pub pools: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+//checks the curve and fee tier, takes the creation fee and adds the pool to the registry
+#[allow(clippy::too_many_arguments)]
+fn register_new_pool<'info>(
+    config: &mut AmmConfigAccountShape,
+    page: &mut PoolRegistryPageAccountShape,
+    page_bump: u8,
+    pool: Pubkey,
+    curve_bit: u8,
+    fee_tier_bps: u16,
+    payer: &Signer<'info>,
+    fee_receiver: &UncheckedAccount<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    if !config.allows_curve(curve_bit) {
+        return err!(ConfigErrors::CurveNotAllowed);
+    }
+    if !config.fee_tiers.contains(&fee_tier_bps) {
+        return err!(ConfigErrors::FeeTierNotAllowed);
+    }
+
+    if config.pool_creation_fee > 0 {
+        let cpi_accounts = system_program::Transfer {
+            from: payer.to_account_info(),
+            to: fee_receiver.to_account_info(),
+        };
+        let cpi_context = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+        system_program::transfer(cpi_context, config.pool_creation_fee)?;
+    }
+
+    //a new page gets its index on the first pool
+    if page.pools.is_empty() {
+        page.page_index = config.pool_count / POOLS_PER_REGISTRY_PAGE;
+        page.bump = page_bump;
+    }
+    page.pools.push(pool);
+    config.pool_count += 1;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    pub signer: Signer<'info>,
+
+    //pays the rent, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(init, payer = payer, space = 8 + AmmConfigAccountShape::INIT_SPACE, seeds = [b"amm_config_v3"], bump)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+
+    //only the upgrade authority of this program can create the config
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ConfigErrors::Unauthorized)]
+    pub program: Program<'info, crate::program::SpoolAmm>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(signer.key()) @ ConfigErrors::Unauthorized)]
+    pub program_data: Box<Account<'info, ProgramData>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeConfig<'info> {
+    fn init_main(&mut self, bump: u8, params: AmmConfigParams) -> Result<()> {
+        let config = &mut self.amm_config;
+        config.bump = bump;
+        config.apply(params)
+    }
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(mut, seeds = [b"amm_config_v3"], bump = amm_config.bump, has_one = admin @ ConfigErrors::Unauthorized)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+}
+
+//---------- reserves ----------
+#[error_code]
+pub enum ReserveErrors {
+    #[msg("vault does not belong to the pool")]
+    InvalidVault,
+
+    #[msg("amount leaving the vault is above the reserve")]
+    ReserveUnderflow,
+}
+
+#[derive(Accounts)]
+pub struct SyncReserves<'info> {
+    #[account(mut, constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    #[account(address = pool_state_account.usdc_vault_address)]
+    pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = pool_state_account.wsol_vault_address)]
+    pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+}
+
+impl<'info> SyncReserves<'info> {
+    fn sync_main(&mut self) {
+        let pool = &mut self.pool_state_account;
+        pool.usdc_reserve = self
+            .usdc_vault_account
+            .amount
+            .saturating_sub(pool.unclaimed_usdc_fees);
+        pool.wsol_reserve = self
+            .wsol_vault_account
+            .amount
+            .saturating_sub(pool.unclaimed_wsol_fees);
+    }
+}
+
+#[derive(Accounts)]
+pub struct SkimReserves<'info> {
+    #[account(constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    #[account(address = pool_state_account.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool_state_account.wsol_mint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, address = pool_state_account.usdc_vault_address)]
+    pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool_state_account.wsol_vault_address)]
+    pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //receive the excess
+    #[account(mut, token::mint = usdc_mint)]
+    pub recipient_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = wsol_mint)]
+    pub recipient_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> SkimReserves<'info> {
+    fn skim_main(&self) -> Result<()> {
+        let usdc_excess = self.pool_state_account.excess(&self.usdc_vault_account);
+        let wsol_excess = self.pool_state_account.excess(&self.wsol_vault_account);
+        self.transfer_excess(
+            &self.usdc_mint,
+            &self.usdc_vault_account,
+            &self.recipient_usdc_account,
+            usdc_excess,
+        )?;
+        self.transfer_excess(
+            &self.wsol_mint,
+            &self.wsol_vault_account,
+            &self.recipient_wsol_account,
+            wsol_excess,
+        )?;
+        Ok(())
+    }
+
+    fn transfer_excess(
+        &self,
+        mint: &InterfaceAccount<'info, Mint>,
+        vault: &InterfaceAccount<'info, TokenAccount>,
+        to: &InterfaceAccount<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let cpi_accounts = TransferChecked {
+            mint: mint.to_account_info(),
+            from: vault.to_account_info(),
+            to: to.to_account_info(),
+            authority: self.pool_state_account.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_state_account.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount, mint.decimals)
+        })?;
+        Ok(())
+    }
+}
+
+//---------- pool migration ----------
+#[error_code]
+pub enum MigrationErrors {
+    #[msg("account is not a pool of this program")]
+    NotAPool,
+
+    #[msg("pool is already on the current version")]
+    AlreadyCurrent,
+
+    #[msg("pool size does not match any layout")]
+    UnknownLayout,
+
+    #[msg("vault or lp mint does not belong to the pool")]
+    AccountMismatch,
+
+    #[msg("vault holds less than the unclaimed fees")]
+    VaultBelowFees,
+}
+
+#[derive(Accounts)]
+pub struct MigratePool<'info> {
+    //pays the rent of the extra space and the locked lp account, anyone can migrate
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: older layouts do not deserialize, owner and discriminator are checked in migrate_main
+    #[account(mut, owner = crate::ID)]
+    pub pool_state_account: UncheckedAccount<'info>,
+
+    //the admin becomes the creator of pools that did not store one
+    #[account(seeds = [b"amm_config_v3"], bump = amm_config.bump)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+
+    //checked against the stored addresses in migrate_main, their balances become the reserves of
+    //layouts that did not track them
+    pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //pools created before the locked minimum get the account close_pool and the first deposit expect
+    #[account(init_if_needed, payer = payer, token::mint = lp_mint, token::authority = pool_state_account, token::token_program = token_program, seeds = [b"locked_lp_v3", pool_state_account.key().as_ref()], bump)]
+    pub locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MigratePool<'info> {
+    fn migrate_main(&self) -> Result<()> {
+        let pool_info = self.pool_state_account.to_account_info();
+        let space = 8 + LpPoolAccountShape::INIT_SPACE;
+        let pool = {
+            let data = pool_info.try_borrow_data()?;
+            if data.len() < 8 || data[..8] != *LpPoolAccountShape::DISCRIMINATOR {
+                return err!(MigrationErrors::NotAPool);
+            }
+
+            if data.len() == space {
+                //versioned layouts keep their size, later versions only fill reserved bytes
+                let mut pool = LpPoolAccountShape::try_deserialize(&mut &data[..])?;
+                if pool.version >= POOL_VERSION {
+                    return err!(MigrationErrors::AlreadyCurrent);
+                }
+                pool.version = POOL_VERSION;
+                pool
+            } else {
+                migration::LegacyPool::decode(&data[8..])?.upgrade(
+                    (
+                        self.usdc_vault_account.amount,
+                        self.wsol_vault_account.amount,
+                    ),
+                    self.lp_mint.supply,
+                    self.amm_config.admin,
+                )?
+            }
+        };
+        if pool.usdc_vault_address != self.usdc_vault_account.key()
+            || pool.wsol_vault_address != self.wsol_vault_account.key()
+            || pool.lp_token_mint != self.lp_mint.key()
+        {
+            return err!(MigrationErrors::AccountMismatch);
+        }
+
+        //grow to the current size, the payer tops up the rent
+        let lamports = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(pool_info.lamports());
+        if lamports > 0 {
+            let cpi_accounts = system_program::Transfer {
+                from: self.payer.to_account_info(),
+                to: pool_info.clone(),
+            };
+            let cpi_context = CpiContext::new(self.system_program.to_account_info(), cpi_accounts);
+            system_program::transfer(cpi_context, lamports)?;
+        }
+        migration::rewrite_pool(&pool_info, &pool)
+    }
+}
+
+//---------- closing pools ----------
+#[error_code]
+pub enum ClosePoolErrors {
+    #[msg("only the config admin or the pool creator can close the pool")]
+    Unauthorized,
+
+    #[msg("pool still has lp outside the locked minimum, fees owed to lps or excess tokens")]
+    PoolNotEmpty,
+
+    #[msg("pool is not on the registry page")]
+    NotRegistered,
+
+    #[msg("lp mint of a closed pool still has supply")]
+    LpSupplyLeft,
+}
+
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    //admin or creator
+    pub authority: Signer<'info>,
+
+    /// CHECK: receives the rent and what is left in the vaults, checked against the pool
+    #[account(mut, address = pool_state_account.creator)]
+    pub creator: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"amm_config_v3"], bump = amm_config.bump)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+
+    //page the pool was registered on, the entry is removed
+    #[account(mut, seeds = [b"pool_registry_v3", registry_page.page_index.to_le_bytes().as_ref()], bump = registry_page.bump)]
+    pub registry_page: Box<Account<'info, PoolRegistryPageAccountShape>>,
+
+    #[account(mut, close = creator, constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked, constraint = authority.key() == pool_state_account.creator || authority.key() == amm_config.admin @ ClosePoolErrors::Unauthorized)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    #[account(address = pool_state_account.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool_state_account.wsol_mint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, address = pool_state_account.usdc_vault_address)]
+    pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool_state_account.wsol_vault_address)]
+    pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //the lp mint can not be closed by the token program, only the locked minimum is burned
+    #[account(mut, address = pool_state_account.lp_token_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, seeds = [b"locked_lp_v3", pool_state_account.key().as_ref()], bump)]
+    pub locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //creator accounts, receive the reserves behind the locked minimum
+    #[account(mut, token::mint = usdc_mint, token::authority = creator)]
+    pub recipient_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = wsol_mint, token::authority = creator)]
+    pub recipient_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ClosePool<'info> {
+    fn close_main(&mut self) -> Result<()> {
+        self.checks()?;
+
+        //pages are not compacted, pool_count keeps pointing at the page being filled
+        let pool_key = self.pool_state_account.key();
+        let pools = &mut self.registry_page.pools;
+        let index = pools
+            .iter()
+            .position(|pool| *pool == pool_key)
+            .ok_or(ClosePoolErrors::NotRegistered)?;
+        pools.remove(index);
+
+        let pool = &self.pool_state_account;
+        pool.with_signer_seeds(|signer_seeds| {
+            let cpi_program = self.token_program.to_account_info();
+
+            //the locked minimum is the only lp left, it goes with the pool
+            let cpi_accounts = Burn {
+                mint: self.lp_mint.to_account_info(),
+                from: self.locked_lp_account.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_context =
+                CpiContext::new_with_signer(cpi_program.clone(), cpi_accounts, signer_seeds);
+            token_interface::burn(cpi_context, self.locked_lp_account.amount)?;
+
+            //reserves behind the locked minimum and the fee dust, everything else was withdrawn
+            for (mint, vault, recipient) in [
+                (
+                    &self.usdc_mint,
+                    &self.usdc_vault_account,
+                    &self.recipient_usdc_account,
+                ),
+                (
+                    &self.wsol_mint,
+                    &self.wsol_vault_account,
+                    &self.recipient_wsol_account,
+                ),
+            ] {
+                if vault.amount > 0 {
+                    let cpi_accounts = TransferChecked {
+                        mint: mint.to_account_info(),
+                        from: vault.to_account_info(),
+                        to: recipient.to_account_info(),
+                        authority: pool.to_account_info(),
+                    };
+                    let cpi_context = CpiContext::new_with_signer(
+                        cpi_program.clone(),
+                        cpi_accounts,
+                        signer_seeds,
+                    );
+                    token_interface::transfer_checked(cpi_context, vault.amount, mint.decimals)?;
+                }
+            }
+
+            //vaults and the locked account, the pool state is closed by anchor
+            for account in [
+                &self.usdc_vault_account,
+                &self.wsol_vault_account,
+                &self.locked_lp_account,
+            ] {
+                let cpi_accounts = CloseAccount {
+                    account: account.to_account_info(),
+                    destination: self.creator.to_account_info(),
+                    authority: pool.to_account_info(),
+                };
+                let cpi_context =
+                    CpiContext::new_with_signer(cpi_program.clone(), cpi_accounts, signer_seeds);
+                token_interface::close_account(cpi_context)?;
+            }
+            Ok(())
+        })
+    }
+
+    //drained means every lp but the locked minimum was burned, every checkpoint claimed and
+    //nothing but unclaimable fee dust sits beside the reserves
+    fn checks(&self) -> Result<()> {
+        let pool = &self.pool_state_account;
+        let drained = self.lp_mint.supply == self.locked_lp_account.amount
+            && pool.owed_usdc_fees == 0
+            && pool.owed_wsol_fees == 0
+            && pool.excess(&self.usdc_vault_account) == 0
+            && pool.excess(&self.wsol_vault_account) == 0;
+        if !drained {
+            return err!(ClosePoolErrors::PoolNotEmpty);
+        }
+        Ok(())
+    }
+}
+
+//---------- liquidity migration ----------
+#[error_code]
+pub enum MigrateLiquidityErrors {
+    #[msg("target pool must be a different pool of the same pair")]
+    PoolMismatch,
+
+    #[msg("withdrawn amounts or minted lp are below the minimum")]
+    SlippageExceeded,
+}
+
+#[derive(Accounts)]
+pub struct MigrateLiquidity<'info> {
+    //owns the lp on both sides
+    pub signer: Signer<'info>,
+
+    //pays for the target lp account, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(address = source_pool.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = source_pool.wsol_mint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //receive what the target ratio leaves over, any account of the pool mints
+    #[account(mut, token::mint = usdc_mint)]
+    pub user_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = wsol_mint)]
+    pub user_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    //---------- pool the liquidity leaves ----------
+    #[account(mut, constraint = !source_pool.locked @ FlashLoanErrors::PoolLocked)]
+    pub source_pool: Box<Account<'info, LpPoolAccountShape>>,
+    #[account(mut, address = source_pool.usdc_vault_address)]
+    pub source_usdc_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = source_pool.wsol_vault_address)]
+    pub source_wsol_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = source_pool.lp_token_mint)]
+    pub source_lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, token::mint = source_lp_mint, token::authority = signer)]
+    pub source_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    //claimable pools only, see checkpoint_before_lp_change
+    #[account(mut, seeds = [b"fee_checkpoint_v3", source_pool.key().as_ref(), signer.key().as_ref()], bump = source_fee_checkpoint.bump, constraint = source_fee_checkpoint.lp_account == source_lp_account.key() @ FeeErrors::InvalidLpAccount)]
+    pub source_fee_checkpoint: Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+    //needed while the protocol fee is on, see mint_protocol_fee_lp
+    #[account(mut, address = source_pool.protocol_fee_account @ ProtocolFeeErrors::InvalidFeeAccount)]
+    pub source_protocol_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    //---------- pool the liquidity joins ----------
+    #[account(mut, constraint = !target_pool.locked @ FlashLoanErrors::PoolLocked, constraint = target_pool.key() != source_pool.key() && target_pool.usdc_mint == source_pool.usdc_mint && target_pool.wsol_mint == source_pool.wsol_mint @ MigrateLiquidityErrors::PoolMismatch)]
+    pub target_pool: Box<Account<'info, LpPoolAccountShape>>,
+    #[account(mut, address = target_pool.usdc_vault_address)]
+    pub target_usdc_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = target_pool.wsol_vault_address)]
+    pub target_wsol_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = target_pool.lp_token_mint)]
+    pub target_lp_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(init_if_needed, payer = payer, associated_token::mint = target_lp_mint, associated_token::authority = signer, associated_token::token_program = token_program)]
+    pub target_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    //claimable pools only, see checkpoint_before_lp_change
+    #[account(mut, seeds = [b"fee_checkpoint_v3", target_pool.key().as_ref(), signer.key().as_ref()], bump = target_fee_checkpoint.bump, constraint = target_fee_checkpoint.lp_account == target_lp_account.key() @ FeeErrors::InvalidLpAccount)]
+    pub target_fee_checkpoint: Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+    //needed while the protocol fee is on, see mint_protocol_fee_lp
+    #[account(mut, address = target_pool.protocol_fee_account @ ProtocolFeeErrors::InvalidFeeAccount)]
+    pub target_protocol_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    //receives MINIMUM_LIQUIDITY when the target pool is empty
+    #[account(mut, seeds = [b"locked_lp_v3", target_pool.key().as_ref()], bump)]
+    pub target_locked_lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MigrateLiquidity<'info> {
+    //withdraw on the source curve and deposit at the target ratio, what the ratio leaves
+    //over goes back to the user, an empty target takes everything at the source price
+    fn migrate_liquidity_main(
+        &mut self,
+        burn_amount: u64,
+        min_usdc_out: u64,
+        min_wsol_out: u64,
+        min_lp_out: u64,
+    ) -> Result<()> {
+        //protocol fees first, both sides are priced against the supply after them
+        mint_protocol_fee_lp(
+            &self.source_pool,
+            &mut self.source_lp_mint,
+            &self.source_protocol_fee_account,
+            &self.source_usdc_vault,
+            &self.source_wsol_vault,
+            &self.token_program,
+        )?;
+        mint_protocol_fee_lp(
+            &self.target_pool,
+            &mut self.target_lp_mint,
+            &self.target_protocol_fee_account,
+            &self.target_usdc_vault,
+            &self.target_wsol_vault,
+            &self.token_program,
+        )?;
+
+        //pro rata amounts, same as remove_liquidity
+        if self.source_lp_mint.supply == 0 {
+            return err!(RemoveLiquidityErrors::EmptyPool);
+        }
+        let (usdc_amount, wsol_amount) = self
+            .source_pool
+            .curve()
+            .withdraw(
+                burn_amount,
+                self.source_pool.usdc_reserve,
+                self.source_pool.wsol_reserve,
+                self.source_lp_mint.supply,
+            )
+            .ok_or(CurveErrors::CurveMathError)?;
+        if usdc_amount < min_usdc_out || wsol_amount < min_wsol_out {
+            return err!(MigrateLiquidityErrors::SlippageExceeded);
+        }
+
+        let (usdc_deposit, wsol_deposit) = if self.target_lp_mint.supply == 0 {
+            (usdc_amount, wsol_amount)
+        } else {
+            curve::amounts_at_ratio(
+                usdc_amount,
+                wsol_amount,
+                self.target_pool.usdc_reserve,
+                self.target_pool.wsol_reserve,
+            )
+            .ok_or(CurveErrors::CurveMathError)?
+        };
+        let lp_amount = self.target_pool.deposit_lp(
+            usdc_deposit,
+            wsol_deposit,
+            self.target_pool.usdc_reserve,
+            self.target_pool.wsol_reserve,
+            self.target_lp_mint.supply,
+        )?;
+        if lp_amount < min_lp_out {
+            return err!(MigrateLiquidityErrors::SlippageExceeded);
+        }
+
+        self.burn_source_lp(burn_amount)?;
+        self.move_tokens(
+            (&self.target_usdc_vault, &self.target_wsol_vault),
+            usdc_deposit,
+            wsol_deposit,
+        )?;
+        self.move_tokens(
+            (&self.user_usdc_account, &self.user_wsol_account),
+            usdc_amount - usdc_deposit,
+            wsol_amount - wsol_deposit,
+        )?;
+        let (usdc_vault, wsol_vault) = (self.source_usdc_vault.key(), self.source_wsol_vault.key());
+        self.source_pool.debit_reserve(usdc_vault, usdc_amount)?;
+        self.source_pool.debit_reserve(wsol_vault, wsol_amount)?;
+        update_k_last(&mut self.source_pool);
+
+        lock_minimum_liquidity(
+            &self.target_pool,
+            &mut self.target_lp_mint,
+            &self.target_locked_lp_account,
+            &self.token_program,
+        )?;
+        self.mint_target_lp(lp_amount)?;
+        let (usdc_vault, wsol_vault) = (self.target_usdc_vault.key(), self.target_wsol_vault.key());
+        self.target_pool.credit_reserve(usdc_vault, usdc_deposit)?;
+        self.target_pool.credit_reserve(wsol_vault, wsol_deposit)?;
+        update_k_last(&mut self.target_pool);
+        Ok(())
+    }
+
+    fn burn_source_lp(&mut self, amount: u64) -> Result<()> {
+        checkpoint_before_lp_change(
+            &mut self.source_pool,
+            &mut self.source_fee_checkpoint,
+            &self.source_lp_mint,
+            &self.source_lp_account,
+            &self.token_program,
+        )?;
+
+        let cpi_accounts = Burn {
+            mint: self.source_lp_mint.to_account_info(),
+            from: self.source_lp_account.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+        let cpi_context = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        token_interface::burn(cpi_context, amount)?;
+
+        checkpoint_after_lp_change(
+            &mut self.source_pool,
+            &mut self.source_fee_checkpoint,
+            &self.source_lp_mint,
+            &mut self.source_lp_account,
+            &self.token_program,
+        )
+    }
+
+    //out of the source vaults, signed by the source pool
+    fn move_tokens(
+        &self,
+        (usdc_to, wsol_to): (
+            &InterfaceAccount<'info, TokenAccount>,
+            &InterfaceAccount<'info, TokenAccount>,
+        ),
+        usdc_amount: u64,
+        wsol_amount: u64,
+    ) -> Result<()> {
+        let pool = &self.source_pool;
+        pool.with_signer_seeds(|signer_seeds| {
+            for (mint, from, to, amount) in [
+                (
+                    &self.usdc_mint,
+                    &self.source_usdc_vault,
+                    usdc_to,
+                    usdc_amount,
+                ),
+                (
+                    &self.wsol_mint,
+                    &self.source_wsol_vault,
+                    wsol_to,
+                    wsol_amount,
+                ),
+            ] {
+                if amount == 0 {
+                    continue;
+                }
+                let cpi_accounts = TransferChecked {
+                    mint: mint.to_account_info(),
+                    from: from.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: pool.to_account_info(),
+                };
+                let cpi_context = CpiContext::new_with_signer(
+                    self.token_program.to_account_info(),
+                    cpi_accounts,
+                    signer_seeds,
+                );
+                token_interface::transfer_checked(cpi_context, amount, mint.decimals)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn mint_target_lp(&mut self, amount: u64) -> Result<()> {
+        checkpoint_before_lp_change(
+            &mut self.target_pool,
+            &mut self.target_fee_checkpoint,
+            &self.target_lp_mint,
+            &self.target_lp_account,
+            &self.token_program,
+        )?;
+
+        let cpi_accounts = MintTo {
+            mint: self.target_lp_mint.to_account_info(),
+            to: self.target_lp_account.to_account_info(),
+            authority: self.target_pool.to_account_info(),
+        };
+        let pool = &self.target_pool;
+        pool.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            );
+            token_interface::mint_to(cpi_context, amount)
+        })?;
+
+        checkpoint_after_lp_change(
+            &mut self.target_pool,
+            &mut self.target_fee_checkpoint,
+            &self.target_lp_mint,
+            &mut self.target_lp_account,
+            &self.token_program,
+        )
+    }
+}
+
+//---------- protocol fee ----------
+#[error_code]
+pub enum ProtocolFeeErrors {
+    #[msg("protocol fee is on, the protocol fee account is missing")]
+    MissingFeeAccount,
+
+    #[msg("account is not the protocol fee account of the pool")]
+    InvalidFeeAccount,
+}
+
+//protocol share of the sqrt(k) growth since the last liquidity event, minted as lp before
+//the event so the depositor or withdrawer gets the share price after the fee
+fn mint_protocol_fee_lp<'info>(
+    pool: &Account<'info, LpPoolAccountShape>,
+    lp_mint: &mut InterfaceAccount<'info, Mint>,
+    protocol_fee_account: &Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    usdc_vault: &InterfaceAccount<'info, TokenAccount>,
+    wsol_vault: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    //sqrt(k) only measures fee growth on x * y = k
+    if pool.protocol_fee_account == Pubkey::default()
+        || pool.curve_type != CurveType::ConstantProduct
+    {
+        return Ok(());
+    }
+    let fee_account = protocol_fee_account
+        .as_ref()
+        .ok_or(ProtocolFeeErrors::MissingFeeAccount)?;
+
+    let fee_lp = curve::constant_product::protocol_fee_lp(
+        pool.reserve(usdc_vault),
+        pool.reserve(wsol_vault),
+        pool.k_last,
+        lp_mint.supply,
+    )
+    .ok_or(CurveErrors::CurveMathError)?;
+    if fee_lp == 0 {
+        return Ok(());
+    }
+
+    let cpi_accounts = MintTo {
+        mint: lp_mint.to_account_info(),
+        to: fee_account.to_account_info(),
+        authority: pool.to_account_info(),
+    };
+    pool.with_signer_seeds(|signer_seeds| {
+        let cpi_context = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        token_interface::mint_to(cpi_context, fee_lp)
+    })?;
+
+    //the deposit or withdrawal is priced against the new supply
+    lp_mint.reload()?;
+    Ok(())
+}
+
+//reserve product after the liquidity event, the next protocol fee is measured from it
+fn update_k_last(pool: &mut LpPoolAccountShape) {
+    pool.k_last = if pool.protocol_fee_account == Pubkey::default()
+        || pool.curve_type != CurveType::ConstantProduct
+    {
+        0
+    } else {
+        pool.usdc_reserve as u128 * pool.wsol_reserve as u128
+    };
+}
+
+#[derive(Accounts)]
+pub struct SetProtocolFee<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(seeds = [b"amm_config_v3"], bump = amm_config.bump, has_one = admin @ ConfigErrors::Unauthorized)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+
+    #[account(mut)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    //leave out to turn the protocol fee off
+    #[account(token::mint = pool_state_account.lp_token_mint)]
+    pub protocol_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+}
+
+impl<'info> SetProtocolFee<'info> {
+    fn set_main(&mut self) -> Result<()> {
+        let pool = &mut self.pool_state_account;
+        pool.protocol_fee_account = match &self.protocol_fee_account {
+            Some(account) => account.key(),
+            None => Pubkey::default(),
+        };
+
+        //growth before this point is not charged, k_last is set by the next liquidity event
+        pool.k_last = 0;
+        Ok(())
+    }
+}
+
+//---------- claimable fees ----------
+#[error_code]
+pub enum FeeErrors {
+    #[msg("pool does not track claimable fees")]
+    NotClaimable,
+
+    #[msg("lp account does not belong to the checkpoint owner")]
+    InvalidLpAccount,
+
+    #[msg("claimable pools need the fee checkpoint to move lp tokens")]
+    MissingCheckpoint,
+
+    #[msg("fee math failed")]
+    FeeMathError,
+}
+
+//per lp checkpoint of the pool fee growth
+#[account]
+#[derive(InitSpace)]
+pub struct FeeCheckpointAccountShape {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    //lp token account whose balance earns the fees
+    pub lp_account: Pubkey,
+    //balance at the last checkpoint, fees are paid on the lower of this and the current one
+    //so lp tokens moved in between checkpoints never earn twice
+    pub lp_balance: u64,
+    pub fee_growth_usdc_last_x64: u128,
+    pub fee_growth_wsol_last_x64: u128,
+    pub owed_usdc: u64,
+    pub owed_wsol: u64,
+    pub bump: u8,
+}
+
+impl FeeCheckpointAccountShape {
+    //add the fees earned since the last checkpoint and move it to lp_balance
+    pub fn accrue(&mut self, pool: &mut LpPoolAccountShape, lp_balance: u64) -> Result<()> {
+        let earning_balance = self.lp_balance.min(lp_balance) as u128;
+        let earned_usdc = math::mul_div(
+            earning_balance,
+            pool.fee_growth_usdc_per_lp_x64
+                .wrapping_sub(self.fee_growth_usdc_last_x64),
+            math::Q64,
+        )
+        .and_then(|earned| u64::try_from(earned).ok())
+        .ok_or(FeeErrors::FeeMathError)?;
+        let earned_wsol = math::mul_div(
+            earning_balance,
+            pool.fee_growth_wsol_per_lp_x64
+                .wrapping_sub(self.fee_growth_wsol_last_x64),
+            math::Q64,
+        )
+        .and_then(|earned| u64::try_from(earned).ok())
+        .ok_or(FeeErrors::FeeMathError)?;
+
+        self.owed_usdc = self
+            .owed_usdc
+            .checked_add(earned_usdc)
+            .ok_or(FeeErrors::FeeMathError)?;
+        self.owed_wsol = self
+            .owed_wsol
+            .checked_add(earned_wsol)
+            .ok_or(FeeErrors::FeeMathError)?;
+        pool.owed_usdc_fees = pool
+            .owed_usdc_fees
+            .checked_add(earned_usdc)
+            .ok_or(FeeErrors::FeeMathError)?;
+        pool.owed_wsol_fees = pool
+            .owed_wsol_fees
+            .checked_add(earned_wsol)
+            .ok_or(FeeErrors::FeeMathError)?;
+        self.fee_growth_usdc_last_x64 = pool.fee_growth_usdc_per_lp_x64;
+        self.fee_growth_wsol_last_x64 = pool.fee_growth_wsol_per_lp_x64;
+        self.lp_balance = lp_balance;
+        Ok(())
+    }
+}
+
+//claimable pools keep checkpointed lp accounts frozen, so their balance only moves
+//through the deposit and withdraw paths, which accrue the checkpoint first
+fn checkpoint_before_lp_change<'info>(
+    pool: &mut Account<'info, LpPoolAccountShape>,
+    fee_checkpoint: &mut Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+    lp_mint: &InterfaceAccount<'info, Mint>,
+    lp_account: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    if pool.fee_mode != FeeMode::Claimable {
+        return Ok(());
+    }
+    let checkpoint = fee_checkpoint
+        .as_mut()
+        .ok_or(FeeErrors::MissingCheckpoint)?;
+    checkpoint.accrue(pool, lp_account.amount)?;
+
+    if lp_account.is_frozen() {
+        let cpi_accounts = ThawAccount {
+            account: lp_account.to_account_info(),
+            mint: lp_mint.to_account_info(),
+            authority: pool.to_account_info(),
+        };
+        pool.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            );
+            token_interface::thaw_account(cpi_context)
+        })?;
+    }
+    Ok(())
+}
+
+//record the new balance and freeze the lp account again
+fn checkpoint_after_lp_change<'info>(
+    pool: &mut Account<'info, LpPoolAccountShape>,
+    fee_checkpoint: &mut Option<Box<Account<'info, FeeCheckpointAccountShape>>>,
+    lp_mint: &InterfaceAccount<'info, Mint>,
+    lp_account: &mut InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    if pool.fee_mode != FeeMode::Claimable {
+        return Ok(());
+    }
+    let checkpoint = fee_checkpoint
+        .as_mut()
+        .ok_or(FeeErrors::MissingCheckpoint)?;
+    lp_account.reload()?;
+    pool.checkpointed_lp_supply = pool
+        .checkpointed_lp_supply
+        .checked_sub(checkpoint.lp_balance)
+        .and_then(|supply| supply.checked_add(lp_account.amount))
+        .ok_or(FeeErrors::FeeMathError)?;
+    checkpoint.lp_balance = lp_account.amount;
+    freeze_lp_account(pool, lp_mint, lp_account, token_program)
+}
+
+fn freeze_lp_account<'info>(
+    pool: &Account<'info, LpPoolAccountShape>,
+    lp_mint: &InterfaceAccount<'info, Mint>,
+    lp_account: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let cpi_accounts = FreezeAccount {
+        account: lp_account.to_account_info(),
+        mint: lp_mint.to_account_info(),
+        authority: pool.to_account_info(),
+    };
+    pool.with_signer_seeds(|signer_seeds| {
+        let cpi_context = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        token_interface::freeze_account(cpi_context)
+    })?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct OpenFeeCheckpoint<'info> {
+    pub signer: Signer<'info>,
+
+    //pays the rent, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut, has_one = lp_token_mint, constraint = pool_state_account.fee_mode == FeeMode::Claimable @ FeeErrors::NotClaimable)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //same lp account providelp mints into
+    #[account(init_if_needed, payer = payer, associated_token::mint = lp_token_mint, associated_token::authority = signer, associated_token::token_program = token_program)]
+    pub lp_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    #[account(init, payer = payer, space = 8 + FeeCheckpointAccountShape::INIT_SPACE, seeds = [b"fee_checkpoint_v3", pool_state_account.key().as_ref(), signer.key().as_ref()], bump)]
+    pub fee_checkpoint: Box<Account<'info, FeeCheckpointAccountShape>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> OpenFeeCheckpoint<'info> {
+    fn open_main(&mut self, bump: u8) -> Result<()> {
+        //starts at the current growth, earlier fees belong to the previous holders
+        let pool = &mut self.pool_state_account;
+        pool.checkpointed_lp_supply = pool
+            .checkpointed_lp_supply
+            .checked_add(self.lp_ata.amount)
+            .ok_or(FeeErrors::FeeMathError)?;
+        let checkpoint = &mut self.fee_checkpoint;
+        checkpoint.pool = pool.key();
+        checkpoint.owner = self.signer.key();
+        checkpoint.lp_account = self.lp_ata.key();
+        checkpoint.lp_balance = self.lp_ata.amount;
+        checkpoint.fee_growth_usdc_last_x64 = pool.fee_growth_usdc_per_lp_x64;
+        checkpoint.fee_growth_wsol_last_x64 = pool.fee_growth_wsol_per_lp_x64;
+        checkpoint.bump = bump;
+
+        if !self.lp_ata.is_frozen() {
+            freeze_lp_account(
+                &self.pool_state_account,
+                &self.lp_token_mint,
+                &self.lp_ata,
+                &self.token_program,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ClaimFees<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(address = pool_state_account.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool_state_account.wsol_mint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, constraint = !pool_state_account.locked @ FlashLoanErrors::PoolLocked)]
+    pub pool_state_account: Box<Account<'info, LpPoolAccountShape>>,
+
+    #[account(mut, seeds = [b"fee_checkpoint_v3", pool_state_account.key().as_ref(), signer.key().as_ref()], bump = fee_checkpoint.bump)]
+    pub fee_checkpoint: Box<Account<'info, FeeCheckpointAccountShape>>,
+
+    //balance that earned the fees, still owned by the signer
+    #[account(address = fee_checkpoint.lp_account @ FeeErrors::InvalidLpAccount, token::authority = signer)]
+    pub lp_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool_state_account.usdc_vault_address)]
+    pub usdc_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool_state_account.wsol_vault_address)]
+    pub wsol_vault_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = usdc_mint, token::authority = signer)]
+    pub user_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = wsol_mint, token::authority = signer)]
+    pub user_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ClaimFees<'info> {
+    fn claim_main(&mut self) -> Result<()> {
+        self.fee_checkpoint
+            .accrue(&mut self.pool_state_account, self.lp_account.amount)?;
+
+        let owed_usdc = self.fee_checkpoint.owed_usdc;
+        let owed_wsol = self.fee_checkpoint.owed_wsol;
+        self.fee_checkpoint.owed_usdc = 0;
+        self.fee_checkpoint.owed_wsol = 0;
+
+        //claims are rounded down so they never exceed what was accrued
+        let pool = &mut self.pool_state_account;
+        //migrated pools start from an estimate of what is owed, it never goes below zero
+        pool.owed_usdc_fees = pool.owed_usdc_fees.saturating_sub(owed_usdc);
+        pool.owed_wsol_fees = pool.owed_wsol_fees.saturating_sub(owed_wsol);
+        pool.unclaimed_usdc_fees = pool
+            .unclaimed_usdc_fees
+            .checked_sub(owed_usdc)
+            .ok_or(FeeErrors::FeeMathError)?;
+        pool.unclaimed_wsol_fees = pool
+            .unclaimed_wsol_fees
+            .checked_sub(owed_wsol)
+            .ok_or(FeeErrors::FeeMathError)?;
+
+        self.transferoutput(
+            &self.usdc_mint,
+            &self.usdc_vault_account,
+            &self.user_usdc_account,
+            owed_usdc,
+        )?;
+        self.transferoutput(
+            &self.wsol_mint,
+            &self.wsol_vault_account,
+            &self.user_wsol_account,
+            owed_wsol,
+        )?;
+        Ok(())
+    }
+
+    fn transferoutput(
+        &self,
+        mint: &InterfaceAccount<'info, Mint>,
+        from: &InterfaceAccount<'info, TokenAccount>,
+        to: &InterfaceAccount<'info, TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let cpi_accounts = TransferChecked {
+            mint: mint.to_account_info(),
+            from: from.to_account_info(),
+            to: to.to_account_info(),
+            authority: self.pool_state_account.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        self.pool_state_account.with_signer_seeds(|signer_seeds| {
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount, mint.decimals)
+        })?;
+        Ok(())
+    }
+}
+
+//---------- concentrated liquidity ----------
+#[error_code]
+pub enum ClmmErrors {
+    #[msg("tick spacing is out of range")]
+    InvalidTickSpacing,
+
+    #[msg("fee rate is above MAX_FEE_RATE")]
+    InvalidFeeRate,
+
+    #[msg("sqrt price is out of range")]
+    InvalidSqrtPrice,
+
+    #[msg("ticks have to be ordered, inside the range and on the tick spacing")]
+    InvalidTickRange,
+
+    #[msg("tick array start is not on the array boundary")]
+    InvalidTickArray,
+
+    #[msg("tick array does not belong to the pool or is out of order")]
+    TickArrayMismatch,
+
+    #[msg("swap ran past the tick arrays that were passed in")]
+    InsufficientTickArrays,
+
+    #[msg("liquidity is above MAX_LIQUIDITY or below zero")]
+    LiquidityOverflow,
+
+    #[msg("liquidity amount can not be zero")]
+    ZeroLiquidity,
+
+    #[msg("token amount is outside the given bounds")]
+    SlippageExceeded,
+
+    #[msg("clmm math failed")]
+    MathError,
+}
+
+//ticks per tick array, arrays cover TICK_ARRAY_SIZE * tick_spacing ticks
+pub const TICK_ARRAY_SIZE: usize = 16;
+const MAX_TICK_SPACING: u16 = 1_000;
+const MAX_SWAP_TICK_ARRAYS: usize = 3;
+
+#[account]
+#[derive(InitSpace)]
+pub struct ClmmPoolAccountShape {
+    pub usdc_mint: Pubkey,
+    pub wsol_mint: Pubkey,
+    pub usdc_vault_address: Pubkey,
+    pub wsol_vault_address: Pubkey,
+    pub tick_spacing: u16,
+    //bps of the input, see clmm::FEE_RATE_DENOMINATOR
+    pub fee_rate: u16,
+    pub sqrt_price_x64: u128,
+    pub current_tick: i32,
+    //liquidity of the positions in range of the current tick
+    pub liquidity: u128,
+    //fees per unit of liquidity since the pool was created, Q64.64
+    pub fee_growth_global_usdc_x64: u128,
+    pub fee_growth_global_wsol_x64: u128,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct TickState {
+    //liquidity added when the price crosses the tick going up
+    pub liquidity_net: i128,
+    //liquidity of all positions using the tick, zero means uninitialized
+    pub liquidity_gross: u128,
+    //fee growth on the other side of the tick from the current price
+    pub fee_growth_outside_usdc_x64: u128,
+    pub fee_growth_outside_wsol_x64: u128,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TickArrayAccountShape {
+    pub pool: Pubkey,
+    pub start_tick_index: i32,
+    pub ticks: [TickState; TICK_ARRAY_SIZE],
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PositionAccountShape {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    //fee growth inside the range at the last update
+    pub fee_growth_inside_usdc_last_x64: u128,
+    pub fee_growth_inside_wsol_last_x64: u128,
+    //fees earned and not collected yet
+    pub tokens_owed_usdc: u64,
+    pub tokens_owed_wsol: u64,
+    pub bump: u8,
+}
+
+impl ClmmPoolAccountShape {
+    //ticks covered by one tick array
+    pub fn tick_array_span(&self) -> i32 {
+        TICK_ARRAY_SIZE as i32 * self.tick_spacing as i32
+    }
+
+    //start of the tick array holding tick
+    pub fn tick_array_start(&self, tick: i32) -> i32 {
+        tick.div_euclid(self.tick_array_span()) * self.tick_array_span()
+    }
+
+    fn check_tick(&self, tick: i32) -> Result<()> {
+        if !(clmm::MIN_TICK..=clmm::MAX_TICK).contains(&tick)
+            || tick % self.tick_spacing as i32 != 0
+        {
+            return err!(ClmmErrors::InvalidTickRange);
+        }
+        Ok(())
+    }
+
+    //add liquidity_delta at a position boundary, the upper tick removes it going up
+    fn update_tick(
+        &self,
+        tick_state: &mut TickState,
+        tick: i32,
+        liquidity_delta: i128,
+        upper: bool,
+    ) -> Result<()> {
+        //growth so far is counted below the tick when it gets initialized
+        if tick_state.liquidity_gross == 0 && tick <= self.current_tick {
+            tick_state.fee_growth_outside_usdc_x64 = self.fee_growth_global_usdc_x64;
+            tick_state.fee_growth_outside_wsol_x64 = self.fee_growth_global_wsol_x64;
+        }
+
+        tick_state.liquidity_gross = tick_state
+            .liquidity_gross
+            .checked_add_signed(liquidity_delta)
+            .filter(|gross| *gross <= clmm::MAX_LIQUIDITY)
+            .ok_or(ClmmErrors::LiquidityOverflow)?;
+
+        let net_delta = if upper {
+            liquidity_delta.checked_neg()
+        } else {
+            Some(liquidity_delta)
+        };
+        tick_state.liquidity_net = net_delta
+            .and_then(|delta| tick_state.liquidity_net.checked_add(delta))
+            .ok_or(ClmmErrors::LiquidityOverflow)?;
+        Ok(())
+    }
+
+    //flip the outside fee growth and return the liquidity change of the crossing
+    fn cross_tick(&self, tick_state: &mut TickState, a_to_b: bool) -> i128 {
+        tick_state.fee_growth_outside_usdc_x64 = self
+            .fee_growth_global_usdc_x64
+            .wrapping_sub(tick_state.fee_growth_outside_usdc_x64);
+        tick_state.fee_growth_outside_wsol_x64 = self
+            .fee_growth_global_wsol_x64
+            .wrapping_sub(tick_state.fee_growth_outside_wsol_x64);
+
+        //going down the tick is crossed the other way
+        if a_to_b {
+            -tick_state.liquidity_net
+        } else {
+            tick_state.liquidity_net
+        }
+    }
+
+    //exact input swap across ticks, returns the output amount
+    fn swap(
+        &mut self,
+        tick_arrays: &mut [Account<TickArrayAccountShape>],
+        amount_in: u64,
+        a_to_b: bool,
+    ) -> Result<u64> {
+        let mut amount_remaining = amount_in as u128;
+        let mut amount_out: u128 = 0;
+
+        while amount_remaining > 0 {
+            let (next_tick, initialized, past_arrays) = self.next_swap_tick(tick_arrays, a_to_b)?;
+            let sqrt_price_target =
+                clmm::sqrt_price_at_tick(next_tick).ok_or(ClmmErrors::MathError)?;
+
+            let step = clmm::compute_swap_step(
+                self.sqrt_price_x64,
+                sqrt_price_target,
+                self.liquidity,
+                amount_remaining,
+                self.fee_rate,
+                a_to_b,
+            )
+            .ok_or(ClmmErrors::MathError)?;
+
+            amount_remaining -= step.amount_in + step.fee_amount;
+            amount_out += step.amount_out;
+
+            //fee goes to the liquidity in range
+            if self.liquidity > 0 {
+                let growth = math::mul_div(step.fee_amount, clmm::Q64, self.liquidity)
+                    .ok_or(ClmmErrors::MathError)?;
+                if a_to_b {
+                    self.fee_growth_global_usdc_x64 =
+                        self.fee_growth_global_usdc_x64.wrapping_add(growth);
+                } else {
+                    self.fee_growth_global_wsol_x64 =
+                        self.fee_growth_global_wsol_x64.wrapping_add(growth);
+                }
+            }
+
+            if step.sqrt_price_next == sqrt_price_target {
+                //the tick after the last array is not known, it can not be crossed
+                if past_arrays {
+                    return err!(ClmmErrors::InsufficientTickArrays);
+                }
+                if initialized {
+                    let array = tick_arrays
+                        .iter_mut()
+                        .find(|array| array.contains(next_tick, self.tick_spacing))
+                        .ok_or(ClmmErrors::TickArrayMismatch)?;
+                    let liquidity_net =
+                        self.cross_tick(array.tick_mut(next_tick, self.tick_spacing)?, a_to_b);
+                    self.liquidity = self
+                        .liquidity
+                        .checked_add_signed(liquidity_net)
+                        .ok_or(ClmmErrors::LiquidityOverflow)?;
+                }
+                self.current_tick = if a_to_b { next_tick - 1 } else { next_tick };
+            } else {
+                self.current_tick =
+                    clmm::tick_at_sqrt_price(step.sqrt_price_next).ok_or(ClmmErrors::MathError)?;
+            }
+            self.sqrt_price_x64 = step.sqrt_price_next;
+        }
+
+        Ok(u64::try_from(amount_out).map_err(|_| ClmmErrors::MathError)?)
+    }
+
+    //next initialized tick in the swap direction, falls back to the end of the last array
+    //returns the tick, whether it is initialized and whether it lies past the arrays
+    fn next_swap_tick(
+        &self,
+        tick_arrays: &[Account<TickArrayAccountShape>],
+        a_to_b: bool,
+    ) -> Result<(i32, bool, bool)> {
+        let last = tick_arrays
+            .last()
+            .ok_or(ClmmErrors::InsufficientTickArrays)?;
+        if a_to_b && self.current_tick < last.start_tick_index {
+            return err!(ClmmErrors::InsufficientTickArrays);
+        }
+
+        for array in tick_arrays {
+            if let Some(tick) =
+                array.next_initialized_tick(self.current_tick, self.tick_spacing, a_to_b)
+            {
+                return Ok((tick, true, false));
+            }
+        }
+
+        //the lowest tick of the last array is still in the arrays going down
+        if a_to_b {
+            Ok((last.start_tick_index.max(clmm::MIN_TICK), false, false))
+        } else {
+            let end = last.start_tick_index + self.tick_array_span();
+            Ok((end.min(clmm::MAX_TICK), false, true))
+        }
+    }
+}
+
+impl TickArrayAccountShape {
+    fn contains(&self, tick: i32, tick_spacing: u16) -> bool {
+        let span = TICK_ARRAY_SIZE as i32 * tick_spacing as i32;
+        tick >= self.start_tick_index && tick < self.start_tick_index + span
+    }
+
+    fn tick_mut(&mut self, tick: i32, tick_spacing: u16) -> Result<&mut TickState> {
+        if !self.contains(tick, tick_spacing) || tick % tick_spacing as i32 != 0 {
+            return err!(ClmmErrors::TickArrayMismatch);
+        }
+        let index = (tick - self.start_tick_index) / tick_spacing as i32;
+        Ok(&mut self.ticks[index as usize])
+    }
+
+    //going down the largest initialized tick at or below tick, going up the smallest above it
+    fn next_initialized_tick(&self, tick: i32, tick_spacing: u16, a_to_b: bool) -> Option<i32> {
+        let spacing = tick_spacing as i32;
+        let ticks = self
+            .ticks
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.liquidity_gross > 0)
+            .map(|(index, _)| self.start_tick_index + index as i32 * spacing);
+
+        if a_to_b {
+            ticks.filter(|initialized| *initialized <= tick).max()
+        } else {
+            ticks.filter(|initialized| *initialized > tick).min()
+        }
+    }
+}
+
+impl PositionAccountShape {
+    //checkpoint the fees earned so far and apply the liquidity change
+    fn update(
+        &mut self,
+        pool: &ClmmPoolAccountShape,
+        lower: &TickState,
+        upper: &TickState,
+        liquidity_delta: i128,
+    ) -> Result<()> {
+        let inside_usdc = clmm::fee_growth_inside(
+            pool.current_tick,
+            self.tick_lower,
+            lower.fee_growth_outside_usdc_x64,
+            self.tick_upper,
+            upper.fee_growth_outside_usdc_x64,
+            pool.fee_growth_global_usdc_x64,
+        );
+        let inside_wsol = clmm::fee_growth_inside(
+            pool.current_tick,
+            self.tick_lower,
+            lower.fee_growth_outside_wsol_x64,
+            self.tick_upper,
+            upper.fee_growth_outside_wsol_x64,
+            pool.fee_growth_global_wsol_x64,
+        );
+
+        let earned_usdc = clmm::fees_earned(
+            self.liquidity,
+            inside_usdc,
+            self.fee_growth_inside_usdc_last_x64,
+        )
+        .ok_or(ClmmErrors::MathError)?;
+        let earned_wsol = clmm::fees_earned(
+            self.liquidity,
+            inside_wsol,
+            self.fee_growth_inside_wsol_last_x64,
+        )
+        .ok_or(ClmmErrors::MathError)?;
+        self.tokens_owed_usdc = self
+            .tokens_owed_usdc
+            .checked_add(earned_usdc)
+            .ok_or(ClmmErrors::MathError)?;
+        self.tokens_owed_wsol = self
+            .tokens_owed_wsol
+            .checked_add(earned_wsol)
+            .ok_or(ClmmErrors::MathError)?;
+        self.fee_growth_inside_usdc_last_x64 = inside_usdc;
+        self.fee_growth_inside_wsol_last_x64 = inside_wsol;
+
+        self.liquidity = self
+            .liquidity
+            .checked_add_signed(liquidity_delta)
+            .ok_or(ClmmErrors::LiquidityOverflow)?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(tick_spacing: u16)]
+pub struct InitializeClmmPool<'info> {
+    //pays the rent, anyone can create a pool
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+    pub wsol_mint: InterfaceAccount<'info, Mint>,
+
+    //one pool per pair and tick spacing
+    #[account(init, payer = payer, space = 8 + ClmmPoolAccountShape::INIT_SPACE, seeds = [b"clmm_pool_v3", usdc_mint.key().as_ref(), wsol_mint.key().as_ref(), &tick_spacing.to_le_bytes()], bump)]
+    pub clmm_pool: Box<Account<'info, ClmmPoolAccountShape>>,
+
+    #[account(init, payer = payer, token::mint = usdc_mint, token::authority = clmm_pool, token::token_program = token_program, seeds = [b"clmm_usdc_vault_v3", clmm_pool.key().as_ref()], bump)]
+    pub usdc_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(init, payer = payer, token::mint = wsol_mint, token::authority = clmm_pool, token::token_program = token_program, seeds = [b"clmm_wsol_vault_v3", clmm_pool.key().as_ref()], bump)]
+    pub wsol_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+
+    //shared settings, CLMM_CURVE_BIT has to be allowed there
+    #[account(mut, seeds = [b"amm_config_v3"], bump = amm_config.bump)]
+    pub amm_config: Box<Account<'info, AmmConfigAccountShape>>,
+
+    //registry page the pool is added to, see register_new_pool
+    #[account(init_if_needed, payer = payer, space = 8 + PoolRegistryPageAccountShape::INIT_SPACE, seeds = [b"pool_registry_v3", (amm_config.pool_count / POOLS_PER_REGISTRY_PAGE).to_le_bytes().as_ref()], bump)]
+    pub registry_page: Box<Account<'info, PoolRegistryPageAccountShape>>,
+
+    /// CHECK: only receives the creation fee, checked against the config
+    #[account(mut, address = amm_config.fee_receiver @ ConfigErrors::InvalidFeeReceiver)]
+    pub fee_receiver: UncheckedAccount<'info>,
+}
+
+impl<'info> InitializeClmmPool<'info> {
+    fn initialize_main(
+        &mut self,
+        tick_spacing: u16,
+        fee_rate: u16,
+        initial_sqrt_price_x64: u128,
+        bump: u8,
+    ) -> Result<()> {
+        if tick_spacing == 0 || tick_spacing > MAX_TICK_SPACING {
+            return err!(ClmmErrors::InvalidTickSpacing);
+        }
+        if fee_rate > clmm::MAX_FEE_RATE {
+            return err!(ClmmErrors::InvalidFeeRate);
+        }
+        let current_tick =
+            clmm::tick_at_sqrt_price(initial_sqrt_price_x64).ok_or(ClmmErrors::InvalidSqrtPrice)?;
+
+        let pool = &mut self.clmm_pool;
+        pool.usdc_mint = self.usdc_mint.key();
+        pool.wsol_mint = self.wsol_mint.key();
+        pool.usdc_vault_address = self.usdc_vault.key();
+        pool.wsol_vault_address = self.wsol_vault.key();
+        pool.tick_spacing = tick_spacing;
+        pool.fee_rate = fee_rate;
+        pool.sqrt_price_x64 = initial_sqrt_price_x64;
+        pool.current_tick = current_tick;
+        pool.bump = bump;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(start_tick_index: i32)]
+pub struct InitializeTickArray<'info> {
+    //pays the rent, anyone can add a tick array
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub clmm_pool: Box<Account<'info, ClmmPoolAccountShape>>,
+
+    #[account(init, payer = payer, space = 8 + TickArrayAccountShape::INIT_SPACE, seeds = [b"tick_array_v3", clmm_pool.key().as_ref(), &start_tick_index.to_le_bytes()], bump)]
+    pub tick_array: Box<Account<'info, TickArrayAccountShape>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeTickArray<'info> {
+    fn initialize_main(&mut self, start_tick_index: i32) -> Result<()> {
+        //the array has to start on its boundary and overlap the tick range
+        let span = self.clmm_pool.tick_array_span();
+        if self.clmm_pool.tick_array_start(start_tick_index) != start_tick_index
+            || start_tick_index + span <= clmm::MIN_TICK
+            || start_tick_index > clmm::MAX_TICK
+        {
+            return err!(ClmmErrors::InvalidTickArray);
+        }
+
+        self.tick_array.pool = self.clmm_pool.key();
+        self.tick_array.start_tick_index = start_tick_index;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(tick_lower: i32, tick_upper: i32)]
+pub struct OpenPosition<'info> {
+    pub owner: Signer<'info>,
+
+    //pays the rent, a relayer can sponsor the transaction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub clmm_pool: Box<Account<'info, ClmmPoolAccountShape>>,
+
+    #[account(init, payer = payer, space = 8 + PositionAccountShape::INIT_SPACE, seeds = [b"position_v3", clmm_pool.key().as_ref(), owner.key().as_ref(), &tick_lower.to_le_bytes(), &tick_upper.to_le_bytes()], bump)]
+    pub position: Box<Account<'info, PositionAccountShape>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> OpenPosition<'info> {
+    fn open_main(&mut self, tick_lower: i32, tick_upper: i32, bump: u8) -> Result<()> {
+        self.clmm_pool.check_tick(tick_lower)?;
+        self.clmm_pool.check_tick(tick_upper)?;
+        if tick_lower >= tick_upper {
+            return err!(ClmmErrors::InvalidTickRange);
+        }
+
+        let position = &mut self.position;
+        position.pool = self.clmm_pool.key();
+        position.owner = self.owner.key();
+        position.tick_lower = tick_lower;
+        position.tick_upper = tick_upper;
+        position.bump = bump;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ModifyClmmLiquidity<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(address = clmm_pool.usdc_mint)]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = clmm_pool.wsol_mint)]
+    pub wsol_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub clmm_pool: Box<Account<'info, ClmmPoolAccountShape>>,
+
+    #[account(mut, has_one = owner, constraint = position.pool == clmm_pool.key() @ ClmmErrors::TickArrayMismatch)]
+    pub position: Box<Account<'info, PositionAccountShape>>,
+
+    //can be the same account, see modify_position
+    #[account(mut, constraint = tick_array_lower.pool == clmm_pool.key() @ ClmmErrors::TickArrayMismatch)]
+    pub tick_array_lower: Box<Account<'info, TickArrayAccountShape>>,
+    #[account(mut, constraint = tick_array_upper.pool == clmm_pool.key() @ ClmmErrors::TickArrayMismatch)]
+    pub tick_array_upper: Box<Account<'info, TickArrayAccountShape>>,
+
+    #[account(mut, address = clmm_pool.usdc_vault_address)]
+    pub usdc_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = clmm_pool.wsol_vault_address)]
+    pub wsol_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = usdc_mint, token::authority = owner)]
+    pub user_usdc_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = wsol_mint, token::authority = owner)]
+    pub user_wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ModifyClmmLiquidity<'info> {
+    fn increase_main(
+        &mut self,
+        liquidity: u128,
+        amount_usdc_max: u64,
+        amount_wsol_max: u64,
+    ) -> Result<()> {
+        let liquidity_delta =
+            i128::try_from(liquidity).map_err(|_| ClmmErrors::LiquidityOverflow)?;
+        if liquidity_delta == 0 {
+            return err!(ClmmErrors::ZeroLiquidity);
+        }
+        self.modify_position(liquidity_delta)?;
+
+        //deposits round up
+        let (amount_usdc, amount_wsol) = self.position_amounts(liquidity, true)?;
+        if amount_usdc > amount_usdc_max || amount_wsol > amount_wsol_max {
+            return err!(ClmmErrors::SlippageExceeded);
+        }
+
+        self.transferinput(
+            &self.user_usdc_account,
+            &self.usdc_vault,
+            &self.usdc_mint,
+            amount_usdc,
+        )?;
+        self.transferinput(
+            &self.user_wsol_account,
+            &self.wsol_vault,
+            &self.wsol_mint,
+            amount_wsol,
+        )?;
+        Ok(())
+    }
+
+    fn decrease_main(
+        &mut self,
+        liquidity: u128,
+        amount_usdc_min: u64,
+        amount_wsol_min: u64,
+    ) -> Result<()> {
+        if liquidity == 0 {
+            return err!(ClmmErrors::ZeroLiquidity);
+        }
+        if liquidity > self.position.liquidity {
+            return err!(ClmmErrors::LiquidityOverflow);
+        }
+        self.modify_position(-(liquidity as i128))?;
+
+        //withdrawals round down
+        let (amount_usdc, amount_wsol) = self.position_amounts(liquidity, false)?;
+        if amount_usdc < amount_usdc_min || amount_wsol < amount_wsol_min {
+            return err!(ClmmErrors::SlippageExceeded);
+        }
+
+        self.transferoutput(
+            &self.usdc_vault,
+            &self.user_usdc_account,
+            &self.usdc_mint,
+            amount_usdc,
+        )?;
+        self.transferoutput(
+            &self.wsol_vault,
+            &self.user_wsol_account,
+            &self.wsol_mint,
+            amount_wsol,
+        )?;
+        Ok(())
+    }
+
+    fn collect_main(&mut self) -> Result<()> {
+        //bring the owed fees up to date first
+        if self.position.liquidity > 0 {
+            self.modify_position(0)?;
+        }
+
+        let amount_usdc = self.position.tokens_owed_usdc;
+        let amount_wsol = self.position.tokens_owed_wsol;
+        self.position.tokens_owed_usdc = 0;
+        self.position.tokens_owed_wsol = 0;
+
+        self.transferoutput(
+            &self.usdc_vault,
+            &self.user_usdc_account,
+            &self.usdc_mint,
+            amount_usdc,
+        )?;
+        self.transferoutput(
+            &self.wsol_vault,
+            &self.user_wsol_account,
+            &self.wsol_mint,
+            amount_wsol,
+        )?;
+        Ok(())
+    }
+
+    //update both ticks, the position fees and the active liquidity
+    fn modify_position(&mut self, liquidity_delta: i128) -> Result<()> {
+        let pool = &mut self.clmm_pool;
+        let position = &mut self.position;
+        let spacing = pool.tick_spacing;
+        let (tick_lower, tick_upper) = (position.tick_lower, position.tick_upper);
+
+        //when both ticks sit in one array only tick_array_upper is changed,
+        //it is written back after tick_array_lower so its data wins
+        let same_array = self.tick_array_lower.key() == self.tick_array_upper.key();
+
+        let lower = {
+            let array = if same_array {
+                &mut self.tick_array_upper
+            } else {
+                &mut self.tick_array_lower
+            };
+            let tick_state = array.tick_mut(tick_lower, spacing)?;
+            pool.update_tick(tick_state, tick_lower, liquidity_delta, false)?;
+            *tick_state
+        };
+        let upper = {
+            let tick_state = self.tick_array_upper.tick_mut(tick_upper, spacing)?;
+            pool.update_tick(tick_state, tick_upper, liquidity_delta, true)?;
+            *tick_state
+        };
+
+        position.update(pool, &lower, &upper, liquidity_delta)?;
+
+        if pool.current_tick >= tick_lower && pool.current_tick < tick_upper {
+            pool.liquidity = pool
+                .liquidity
+                .checked_add_signed(liquidity_delta)
+                .filter(|liquidity| *liquidity <= clmm::MAX_LIQUIDITY)
+                .ok_or(ClmmErrors::LiquidityOverflow)?;
+        }
+
+        //ticks nobody uses anymore go back to uninitialized
+        if lower.liquidity_gross == 0 {
+            let array = if same_array {
+                &mut self.tick_array_upper
+            } else {
+                &mut self.tick_array_lower
+            };
+            *array.tick_mut(tick_lower, spacing)? = TickState::default();
+        }
+        if upper.liquidity_gross == 0 {
+            *self.tick_array_upper.tick_mut(tick_upper, spacing)? = TickState::default();
+        }
+        Ok(())
+    }
+
+    fn position_amounts(&self, liquidity: u128, round_up: bool) -> Result<(u64, u64)> {
+        let (amount_usdc, amount_wsol) = clmm::amounts_for_liquidity(
+            self.clmm_pool.sqrt_price_x64,
+            self.position.tick_lower,
+            self.position.tick_upper,
+            liquidity,
+            round_up,
+        )
+        .ok_or(ClmmErrors::MathError)?;
+
+        let amount_usdc = u64::try_from(amount_usdc).map_err(|_| ClmmErrors::MathError)?;
+        let amount_wsol = u64::try_from(amount_wsol).map_err(|_| ClmmErrors::MathError)?;
+        Ok((amount_usdc, amount_wsol))
+    }
+
+    fn transferinput(
+        &self,
+        from: &InterfaceAccount<'info, TokenAccount>,
+        to: &InterfaceAccount<'info, TokenAccount>,
+        mint: &InterfaceAccount<'info, Mint>,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let cpi_accounts = TransferChecked {
+            mint: mint.to_account_info(),
+            from: from.to_account_info(),
+            to: to.to_account_info(),
+            authority: self.owner.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_context, amount, mint.decimals)?;
+        Ok(())
+    }
+
+    fn transferoutput(
+        &self,
+        from: &InterfaceAccount<'info, TokenAccount>,
+        to: &InterfaceAccount<'info, TokenAccount>,
+        mint: &InterfaceAccount<'info, Mint>,
+        amount: u64,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let cpi_accounts = TransferChecked {
+            mint: mint.to_account_info(),
+            from: from.to_account_info(),
+            to: to.to_account_info(),
+            authority: self.clmm_pool.to_account_info(),
+        };
+
+        let usdc_mint = self.clmm_pool.usdc_mint;
+        let wsol_mint = self.clmm_pool.wsol_mint;
+        let tick_spacing = self.clmm_pool.tick_spacing.to_le_bytes();
+        let seeds = [
+            b"clmm_pool_v3",
+            usdc_mint.as_ref(),
+            wsol_mint.as_ref(),
+            tick_spacing.as_ref(),
+            &[self.clmm_pool.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+        token_interface::transfer_checked(cpi_context, amount, mint.decimals)?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SwapClmm<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub clmm_pool: Box<Account<'info, ClmmPoolAccountShape>>,
+
+    #[account(address = input_vault.mint)]
+    pub input_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = output_vault.mint)]
+    pub output_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    //vaults for the swap, checked against the pool in swap_main
+    #[account(mut)]
+    pub input_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub output_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = input_mint, token::authority = signer)]
+    pub user_input_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = output_mint, token::authority = signer)]
+    pub user_output_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> SwapClmm<'info> {
+    fn swap_main(
+        &mut self,
+        amount_in: u64,
+        min_amount_out: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        if amount_in == 0 || self.user_input_account.amount < amount_in {
+            return err!(SwapTokenErrors::AmountError);
+        }
+
+        let pool = &self.clmm_pool;
+        let input_vault = self.input_vault.key();
+        let output_vault = self.output_vault.key();
+        let a_to_b =
+            input_vault == pool.usdc_vault_address && output_vault == pool.wsol_vault_address;
+        let b_to_a =
+            input_vault == pool.wsol_vault_address && output_vault == pool.usdc_vault_address;
+        if !a_to_b && !b_to_a {
+            return err!(SwapTokenErrors::InputVaultError);
+        }
+
+        let mut tick_arrays = self.load_tick_arrays(remaining_accounts, a_to_b)?;
+        let amount_out = self.clmm_pool.swap(&mut tick_arrays, amount_in, a_to_b)?;
+        if amount_out == 0 || amount_out < min_amount_out {
+            return err!(ClmmErrors::SlippageExceeded);
+        }
+        for tick_array in tick_arrays.iter() {
+            tick_array.exit(&crate::ID)?;
+        }
+
+        self.transferinput(amount_in)?;
+        self.transferoutput(amount_out)?;
+        Ok(())
+    }
+
+    //arrays have to follow each other in swap order, starting at the current tick
+    fn load_tick_arrays(
+        &self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        a_to_b: bool,
+    ) -> Result<Vec<Account<'info, TickArrayAccountShape>>> {
+        if remaining_accounts.is_empty() || remaining_accounts.len() > MAX_SWAP_TICK_ARRAYS {
+            return err!(ClmmErrors::InsufficientTickArrays);
+        }
+
+        let pool = &self.clmm_pool;
+        let span = pool.tick_array_span();
+        let mut expected_start = pool.tick_array_start(pool.current_tick);
+        let mut tick_arrays = Vec::with_capacity(remaining_accounts.len());
+        for account in remaining_accounts {
+            let tick_array = Account::<TickArrayAccountShape>::try_from(account)?;
+            if tick_array.pool != pool.key() || tick_array.start_tick_index != expected_start {
+                return err!(ClmmErrors::TickArrayMismatch);
+            }
+            expected_start = if a_to_b {
+                expected_start - span
+            } else {
+                expected_start + span
+            };
+            tick_arrays.push(tick_array);
+        }
+        Ok(tick_arrays)
+    }
+
+    fn transferinput(&self, amount: u64) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: self.input_mint.to_account_info(),
+            from: self.user_input_account.to_account_info(),
+            to: self.input_vault.to_account_info(),
+            authority: self.signer.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_context, amount, self.input_mint.decimals)?;
+        Ok(())
+    }
+
+    fn transferoutput(&self, amount: u64) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            mint: self.output_mint.to_account_info(),
+            from: self.output_vault.to_account_info(),
+            to: self.user_output_account.to_account_info(),
+            authority: self.clmm_pool.to_account_info(),
+        };
+
+        let usdc_mint = self.clmm_pool.usdc_mint;
+        let wsol_mint = self.clmm_pool.wsol_mint;
+        let tick_spacing = self.clmm_pool.tick_spacing.to_le_bytes();
+        let seeds = [
+            b"clmm_pool_v3",
             usdc_mint.as_ref(),
             wsol_mint.as_ref(),
-            &[self.pool_state_account.bump],
+            tick_spacing.as_ref(),
+            &[self.clmm_pool.bump],
         ];
         let signer_seeds = &[&seeds[..]];
 
         let cpi_program = self.token_program.to_account_info();
         let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
-        token_interface::transfer_checked(cpi_context, transfersolamount, decimals)?;
+        token_interface::transfer_checked(cpi_context, amount, self.output_mint.decimals)?;
         Ok(())
     }
 }
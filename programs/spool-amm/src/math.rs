@@ -0,0 +1,234 @@
+//integer helpers shared by the curves
+
+const LOW_MASK: u128 = u64::MAX as u128;
+
+//full 256 bit product of two u128, returned as (high, low)
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a_high, a_low) = (a >> 64, a & LOW_MASK);
+    let (b_high, b_low) = (b >> 64, b & LOW_MASK);
+
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+
+    let middle = (low_low >> 64) + (high_low & LOW_MASK) + (low_high & LOW_MASK);
+    let low = (low_low & LOW_MASK) | (middle << 64);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+    (high, low)
+}
+
+//a * b / denominator rounded down, without overflowing the intermediate product
+//returns None for a zero denominator or a result that does not fit in u128
+pub fn mul_div(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+
+    let (high, low) = full_mul(a, b);
+    if high == 0 {
+        return Some(low / denominator);
+    }
+    if high >= denominator {
+        return None;
+    }
+
+    //long division of the 256 bit product, the high part is the starting remainder
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1 << bit;
+        }
+    }
+    Some(quotient)
+}
+
+//same as mul_div, rounded up
+pub fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    let quotient = mul_div(a, b, denominator)?;
+    let (high, low) = full_mul(quotient, denominator);
+    let (product_high, product_low) = full_mul(a, b);
+    if (high, low) == (product_high, product_low) {
+        Some(quotient)
+    } else {
+        quotient.checked_add(1)
+    }
+}
+
+//floor(sqrt(a * b)) without overflowing the product, newton from above
+pub fn sqrt_of_product(a: u128, b: u128) -> Option<u128> {
+    let mut root = a.max(b);
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    loop {
+        let next = root.checked_add(mul_div(a, b, root)?)? / 2;
+        if next >= root {
+            return Some(root);
+        }
+        root = next;
+    }
+}
+
+//one in Q64.64
+pub const Q64: u128 = 1 << 64;
+
+//a * b >> 128 rounded down, for multiplying Q128 fractions
+pub fn mul_shr_128(a: u128, b: u128) -> u128 {
+    full_mul(a, b).0
+}
+
+//---------- fixed point, 18 decimals ----------
+pub const ONE: u128 = 1_000_000_000_000_000_000;
+const ONE_SIGNED: i128 = ONE as i128;
+const LN_2: i128 = 693_147_180_559_945_309;
+
+//series terms before giving up, both series converge well before this
+const MAX_SERIES_TERMS: i128 = 64;
+
+//relative error bound of pow, callers round by it in favour of the pool
+pub const MAX_POW_RELATIVE_ERROR: u128 = 10_000;
+
+//natural log of a fixed point value
+pub fn ln(x: u128) -> Option<i128> {
+    if x == 0 {
+        return None;
+    }
+
+    //x = m * 2^k with m in [1, 2)
+    let mut k: i128 = 0;
+    let mut m = x;
+    if m >= 2 * ONE {
+        let shift = (128 - (m / ONE).leading_zeros() - 1) as i128;
+        m >>= shift;
+        k = shift;
+    }
+    while m < ONE {
+        m <<= 1;
+        k -= 1;
+    }
+
+    //ln(m) = 2 * atanh((m - 1) / (m + 1)), z stays below 1 / 3
+    let m = m as i128;
+    let z = (m - ONE_SIGNED) * ONE_SIGNED / (m + ONE_SIGNED);
+    let z_squared = z * z / ONE_SIGNED;
+    let mut term = z;
+    let mut sum = z;
+    let mut n = 3;
+    while n < 2 * MAX_SERIES_TERMS {
+        term = term * z_squared / ONE_SIGNED;
+        if term == 0 {
+            break;
+        }
+        sum += term / n;
+        n += 2;
+    }
+
+    k.checked_mul(LN_2)?.checked_add(2 * sum)
+}
+
+//e^x of a fixed point value
+pub fn exp(x: i128) -> Option<u128> {
+    //x = k * ln2 + r with r in [-ln2 / 2, ln2 / 2]
+    let half = if x >= 0 { LN_2 / 2 } else { -LN_2 / 2 };
+    let k = (x + half) / LN_2;
+    let r = x - k * LN_2;
+
+    //taylor series of e^r
+    let mut term = ONE_SIGNED;
+    let mut sum = ONE_SIGNED;
+    for n in 1..MAX_SERIES_TERMS {
+        term = term * r / (n * ONE_SIGNED);
+        if term == 0 {
+            break;
+        }
+        sum += term;
+    }
+    let exp_r = sum as u128;
+
+    if k >= 0 {
+        let shift = k as u32;
+        if shift >= exp_r.leading_zeros() {
+            return None;
+        }
+        Some(exp_r << shift)
+    } else {
+        Some(exp_r.checked_shr((-k) as u32).unwrap_or(0))
+    }
+}
+
+//base^exponent for fixed point values
+pub fn pow(base: u128, exponent: u128) -> Option<u128> {
+    if exponent == 0 || base == ONE {
+        return Some(ONE);
+    }
+    if base == 0 {
+        return Some(0);
+    }
+    let power = ln(base)?.checked_mul(i128::try_from(exponent).ok()?)? / ONE_SIGNED;
+    exp(power)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: u128, expected: f64) -> bool {
+        let actual = actual as f64 / ONE as f64;
+        ((actual - expected) / expected).abs() < 1e-12
+    }
+
+    #[test]
+    fn ln_and_exp_match_floats() {
+        for value in [0.001, 0.5, 1.0, 1.5, 2.0, 10.0, 12345.678, 1e19] {
+            let fixed = (value * ONE as f64) as u128;
+            let log = ln(fixed).unwrap() as f64 / ONE as f64;
+            assert!((log - value.ln()).abs() < 1e-12, "ln({value})");
+            assert!(
+                close(exp(ln(fixed).unwrap()).unwrap(), value),
+                "exp(ln({value}))"
+            );
+        }
+    }
+
+    #[test]
+    fn pow_matches_floats() {
+        assert!(close(pow(ONE / 2, 4 * ONE).unwrap(), 0.0625));
+        assert!(close(
+            pow(9 * ONE / 10, ONE / 4).unwrap(),
+            0.9f64.powf(0.25)
+        ));
+        assert!(close(pow(3 * ONE / 4, 4 * ONE).unwrap(), 0.75f64.powf(4.0)));
+        assert_eq!(pow(ONE / 3, 0), Some(ONE));
+    }
+
+    #[test]
+    fn mul_div_matches_small_values() {
+        assert_eq!(mul_div(10, 20, 3), Some(66));
+        assert_eq!(mul_div_ceil(10, 20, 3), Some(67));
+        assert_eq!(mul_div_ceil(10, 21, 3), Some(70));
+        assert_eq!(mul_div(1, 1, 0), None);
+    }
+
+    #[test]
+    fn sqrt_of_product_is_floored() {
+        assert_eq!(sqrt_of_product(4, 9), Some(6));
+        assert_eq!(sqrt_of_product(3, 5), Some(3));
+        assert_eq!(sqrt_of_product(0, 5), Some(0));
+        assert_eq!(sqrt_of_product(9 << 100, 1 << 94), Some(3 << 97));
+        assert_eq!(sqrt_of_product((9 << 100) + 1, 1 << 94), Some(3 << 97));
+        assert_eq!(sqrt_of_product(1 << 100, 1 << 100), Some(1 << 100));
+    }
+
+    #[test]
+    fn mul_div_handles_wide_products() {
+        let a = u128::MAX / 3;
+        assert_eq!(mul_div(a, 6, 2), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 2, 1), None);
+    }
+}
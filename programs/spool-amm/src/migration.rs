@@ -0,0 +1,431 @@
+//pool layouts written before the version byte and how they map onto the current one
+//an unversioned pool is told apart by its size, every step appended fields except FeeTier
+
+use anchor_lang::prelude::*;
+
+use crate::curve::CurveType;
+use crate::{FeeMode, LpPoolAccountShape, MigrationErrors, POOL_RESERVED_BYTES, POOL_VERSION};
+
+//swap fee every pool charged before fee tiers, 30 / 1000
+pub const LEGACY_FEE_TIER_BPS: u16 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LegacyLayout {
+    //mints, vaults, lp mint and bump
+    Base,
+    //flash loan lock
+    FlashLoan,
+    //amp coefficient, zero kept the pool constant product
+    Amp,
+    //curve type and parameter in place of the amp coefficient
+    Curve,
+    //fee mode and claimable fee growth
+    ClaimableFees,
+    //creator, protocol fee account and k_last
+    ProtocolFee,
+    //tracked reserves
+    Reserves,
+    //fee tier inserted after the curve parameter, the pool seeds include it from here on
+    FeeTier,
+}
+
+impl LegacyLayout {
+    //data size after the discriminator
+    pub fn from_len(len: usize) -> Option<Self> {
+        match len {
+            161 => Some(Self::Base),
+            202 => Some(Self::FlashLoan),
+            210 => Some(Self::Amp),
+            211 => Some(Self::Curve),
+            260 => Some(Self::ClaimableFees),
+            340 => Some(Self::ProtocolFee),
+            356 => Some(Self::Reserves),
+            358 => Some(Self::FeeTier),
+            _ => None,
+        }
+    }
+}
+
+//fields of an unversioned pool, the ones its layout did not have keep the value of a new pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct LegacyPool {
+    pub usdc_mint: Pubkey,
+    pub wsol_mint: Pubkey,
+    pub usdc_vault_address: Pubkey,
+    pub wsol_vault_address: Pubkey,
+    pub lp_token_mint: Pubkey,
+    pub bump: u8,
+    pub locked: bool,
+    pub flash_loan_vault: Pubkey,
+    pub flash_loan_amount: u64,
+    pub curve_type: CurveType,
+    pub curve_parameter: u64,
+    //none when the pool seeds have no fee tier
+    pub fee_tier_bps: Option<u16>,
+    pub fee_mode: FeeMode,
+    pub fee_growth_usdc_per_lp_x64: u128,
+    pub fee_growth_wsol_per_lp_x64: u128,
+    pub unclaimed_usdc_fees: u64,
+    pub unclaimed_wsol_fees: u64,
+    pub creator: Option<Pubkey>,
+    pub protocol_fee_account: Pubkey,
+    pub k_last: u128,
+    //none when the curve priced against the vault balances
+    pub reserves: Option<(u64, u64)>,
+}
+
+impl LegacyPool {
+    //data is the account data after the discriminator
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let layout = LegacyLayout::from_len(data.len()).ok_or(MigrationErrors::UnknownLayout)?;
+        let buf = &mut &data[..];
+
+        let mut pool = LegacyPool {
+            usdc_mint: Pubkey::deserialize(buf)?,
+            wsol_mint: Pubkey::deserialize(buf)?,
+            usdc_vault_address: Pubkey::deserialize(buf)?,
+            wsol_vault_address: Pubkey::deserialize(buf)?,
+            lp_token_mint: Pubkey::deserialize(buf)?,
+            bump: u8::deserialize(buf)?,
+            locked: false,
+            flash_loan_vault: Pubkey::default(),
+            flash_loan_amount: 0,
+            curve_type: CurveType::ConstantProduct,
+            curve_parameter: 0,
+            fee_tier_bps: None,
+            fee_mode: FeeMode::Compound,
+            fee_growth_usdc_per_lp_x64: 0,
+            fee_growth_wsol_per_lp_x64: 0,
+            unclaimed_usdc_fees: 0,
+            unclaimed_wsol_fees: 0,
+            creator: None,
+            protocol_fee_account: Pubkey::default(),
+            k_last: 0,
+            reserves: None,
+        };
+
+        if layout >= LegacyLayout::FlashLoan {
+            pool.locked = bool::deserialize(buf)?;
+            pool.flash_loan_vault = Pubkey::deserialize(buf)?;
+            pool.flash_loan_amount = u64::deserialize(buf)?;
+        }
+        if layout == LegacyLayout::Amp {
+            let amp = u64::deserialize(buf)?;
+            if amp != 0 {
+                pool.curve_type = CurveType::Stable;
+                pool.curve_parameter = amp;
+            }
+        }
+        if layout >= LegacyLayout::Curve {
+            pool.curve_type = CurveType::deserialize(buf)?;
+            pool.curve_parameter = u64::deserialize(buf)?;
+        }
+        if layout >= LegacyLayout::FeeTier {
+            pool.fee_tier_bps = Some(u16::deserialize(buf)?);
+        }
+        if layout >= LegacyLayout::ClaimableFees {
+            pool.fee_mode = FeeMode::deserialize(buf)?;
+            pool.fee_growth_usdc_per_lp_x64 = u128::deserialize(buf)?;
+            pool.fee_growth_wsol_per_lp_x64 = u128::deserialize(buf)?;
+            pool.unclaimed_usdc_fees = u64::deserialize(buf)?;
+            pool.unclaimed_wsol_fees = u64::deserialize(buf)?;
+        }
+        if layout >= LegacyLayout::ProtocolFee {
+            pool.creator = Some(Pubkey::deserialize(buf)?);
+            pool.protocol_fee_account = Pubkey::deserialize(buf)?;
+            pool.k_last = u128::deserialize(buf)?;
+        }
+        if layout >= LegacyLayout::Reserves {
+            pool.reserves = Some((u64::deserialize(buf)?, u64::deserialize(buf)?));
+        }
+        Ok(pool)
+    }
+
+    //current state of the pool
+    //vault_balances price pools that did not track reserves, the unclaimed fees are not part of them
+    //default_creator stands in for pools created before the creator was stored
+    pub fn upgrade(
+        self,
+        vault_balances: (u64, u64),
+        lp_supply: u64,
+        default_creator: Pubkey,
+    ) -> Result<LpPoolAccountShape> {
+        let (usdc_reserve, wsol_reserve) = match self.reserves {
+            Some(reserves) => reserves,
+            None => (
+                vault_balances
+                    .0
+                    .checked_sub(self.unclaimed_usdc_fees)
+                    .ok_or(MigrationErrors::VaultBelowFees)?,
+                vault_balances
+                    .1
+                    .checked_sub(self.unclaimed_wsol_fees)
+                    .ok_or(MigrationErrors::VaultBelowFees)?,
+            ),
+        };
+
+        //the lp held in checkpoints was never counted, the supply is an upper bound so
+        //checkpoints opened before the migration can still be subtracted
+        let checkpointed_lp_supply = match self.fee_mode {
+            FeeMode::Claimable => lp_supply,
+            FeeMode::Compound => 0,
+        };
+
+        Ok(LpPoolAccountShape {
+            usdc_mint: self.usdc_mint,
+            wsol_mint: self.wsol_mint,
+            usdc_vault_address: self.usdc_vault_address,
+            wsol_vault_address: self.wsol_vault_address,
+            lp_token_mint: self.lp_token_mint,
+            bump: self.bump,
+            locked: self.locked,
+            flash_loan_vault: self.flash_loan_vault,
+            flash_loan_amount: self.flash_loan_amount,
+            curve_type: self.curve_type,
+            curve_parameter: self.curve_parameter,
+            fee_tier_bps: self.fee_tier_bps.unwrap_or(LEGACY_FEE_TIER_BPS),
+            fee_mode: self.fee_mode,
+            fee_growth_usdc_per_lp_x64: self.fee_growth_usdc_per_lp_x64,
+            fee_growth_wsol_per_lp_x64: self.fee_growth_wsol_per_lp_x64,
+            unclaimed_usdc_fees: self.unclaimed_usdc_fees,
+            unclaimed_wsol_fees: self.unclaimed_wsol_fees,
+            creator: self.creator.unwrap_or(default_creator),
+            protocol_fee_account: self.protocol_fee_account,
+            k_last: self.k_last,
+            usdc_reserve,
+            wsol_reserve,
+            version: POOL_VERSION,
+            checkpointed_lp_supply,
+            legacy_seeds: self.fee_tier_bps.is_none(),
+            //checkpoints kept no pool wide total, every unclaimed fee counts as owed
+            owed_usdc_fees: self.unclaimed_usdc_fees,
+            owed_wsol_fees: self.unclaimed_wsol_fees,
+            reserved: [0; POOL_RESERVED_BYTES],
+        })
+    }
+}
+
+//grows the pool account to the current size and writes the upgraded state over the old layout
+//the account must already hold the rent of the new size
+pub fn rewrite_pool(pool_info: &AccountInfo, pool: &LpPoolAccountShape) -> Result<()> {
+    pool_info.resize(8 + LpPoolAccountShape::INIT_SPACE)?;
+    pool.try_serialize(&mut &mut pool_info.try_borrow_mut_data()?[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::entrypoint::{
+        self, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER,
+    };
+    use anchor_lang::Discriminator;
+
+    fn key(seed: u8) -> Pubkey {
+        Pubkey::new_from_array([seed; 32])
+    }
+
+    //mints, vaults, lp mint and bump shared by every layout
+    fn base_fields(data: &mut Vec<u8>) {
+        for seed in 1..=5 {
+            key(seed).serialize(data).unwrap();
+        }
+        254u8.serialize(data).unwrap();
+    }
+
+    fn flash_loan_fields(data: &mut Vec<u8>) {
+        false.serialize(data).unwrap();
+        Pubkey::default().serialize(data).unwrap();
+        0u64.serialize(data).unwrap();
+    }
+
+    fn claimable_fee_fields(data: &mut Vec<u8>) {
+        FeeMode::Claimable.serialize(data).unwrap();
+        (7u128 << 64).serialize(data).unwrap();
+        (9u128 << 64).serialize(data).unwrap();
+        11u64.serialize(data).unwrap();
+        13u64.serialize(data).unwrap();
+    }
+
+    fn protocol_fee_fields(data: &mut Vec<u8>) {
+        key(6).serialize(data).unwrap();
+        key(7).serialize(data).unwrap();
+        1_000_000u128.serialize(data).unwrap();
+    }
+
+    //account data of the first layout, as the program wrote it before any of the later steps
+    fn base_layout() -> Vec<u8> {
+        let mut data = LpPoolAccountShape::DISCRIMINATOR.to_vec();
+        base_fields(&mut data);
+        data
+    }
+
+    //serializes one account the way the runtime passes it to the entrypoint, so resize has the
+    //original length and the spare bytes behind the data it expects
+    fn runtime_input(pool_key: &Pubkey, data: &[u8]) -> Vec<u64> {
+        let mut input = Vec::new();
+        input.extend_from_slice(&1u64.to_le_bytes());
+        input.extend_from_slice(&[NON_DUP_MARKER, 0, 1, 0, 0, 0, 0, 0]);
+        input.extend_from_slice(pool_key.as_ref());
+        input.extend_from_slice(crate::ID.as_ref());
+        input.extend_from_slice(&u64::MAX.to_le_bytes());
+        input.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        input.extend_from_slice(data);
+        input.resize(input.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+        input.resize(input.len().next_multiple_of(8), 0);
+        input.extend_from_slice(&0u64.to_le_bytes());
+        input.extend_from_slice(&0u64.to_le_bytes());
+        input.extend_from_slice(crate::ID.as_ref());
+
+        //u64 words keep the buffer aligned like the runtime input
+        input
+            .chunks(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn layout_sizes_match_the_decoder() {
+        let mut data = Vec::new();
+        base_fields(&mut data);
+        assert_eq!(LegacyLayout::from_len(data.len()), Some(LegacyLayout::Base));
+        flash_loan_fields(&mut data);
+        assert_eq!(
+            LegacyLayout::from_len(data.len()),
+            Some(LegacyLayout::FlashLoan)
+        );
+        (CurveType::ConstantProduct, 0u64)
+            .serialize(&mut data)
+            .unwrap();
+        assert_eq!(
+            LegacyLayout::from_len(data.len()),
+            Some(LegacyLayout::Curve)
+        );
+        claimable_fee_fields(&mut data);
+        assert_eq!(
+            LegacyLayout::from_len(data.len()),
+            Some(LegacyLayout::ClaimableFees)
+        );
+        protocol_fee_fields(&mut data);
+        assert_eq!(
+            LegacyLayout::from_len(data.len()),
+            Some(LegacyLayout::ProtocolFee)
+        );
+        (5u64, 6u64).serialize(&mut data).unwrap();
+        assert_eq!(
+            LegacyLayout::from_len(data.len()),
+            Some(LegacyLayout::Reserves)
+        );
+        assert_eq!(
+            LegacyLayout::from_len(data.len() + 2),
+            Some(LegacyLayout::FeeTier)
+        );
+        assert_eq!(LegacyLayout::from_len(LpPoolAccountShape::INIT_SPACE), None);
+    }
+
+    #[test]
+    fn base_layout_prices_against_the_vaults() {
+        let legacy = LegacyPool::decode(&base_layout()[8..]).unwrap();
+        assert_eq!(legacy.usdc_vault_address, key(3));
+        assert_eq!(legacy.bump, 254);
+        assert_eq!(legacy.fee_tier_bps, None);
+
+        let pool = legacy.upgrade((100, 200), 1_000, key(9)).unwrap();
+        assert_eq!((pool.usdc_reserve, pool.wsol_reserve), (100, 200));
+        assert_eq!(pool.fee_tier_bps, LEGACY_FEE_TIER_BPS);
+        assert_eq!(pool.creator, key(9));
+        assert_eq!(pool.curve_type, CurveType::ConstantProduct);
+        assert_eq!(pool.checkpointed_lp_supply, 0);
+        assert_eq!(pool.version, POOL_VERSION);
+        assert!(pool.legacy_seeds);
+    }
+
+    #[test]
+    fn amp_layout_keeps_the_stable_curve() {
+        let mut data = Vec::new();
+        base_fields(&mut data);
+        flash_loan_fields(&mut data);
+        100u64.serialize(&mut data).unwrap();
+
+        let legacy = LegacyPool::decode(&data).unwrap();
+        assert_eq!(legacy.curve_type, CurveType::Stable);
+        assert_eq!(legacy.curve_parameter, 100);
+    }
+
+    #[test]
+    fn claimable_fees_stay_out_of_the_reserves() {
+        let mut data = Vec::new();
+        base_fields(&mut data);
+        flash_loan_fields(&mut data);
+        (CurveType::ConstantProduct, 0u64)
+            .serialize(&mut data)
+            .unwrap();
+        claimable_fee_fields(&mut data);
+        protocol_fee_fields(&mut data);
+
+        let pool = LegacyPool::decode(&data)
+            .unwrap()
+            .upgrade((100, 200), 1_000, key(9))
+            .unwrap();
+        assert_eq!((pool.usdc_reserve, pool.wsol_reserve), (89, 187));
+        assert_eq!(pool.fee_growth_wsol_per_lp_x64, 9u128 << 64);
+        assert_eq!(pool.creator, key(6));
+        assert_eq!(pool.protocol_fee_account, key(7));
+        assert_eq!(pool.checkpointed_lp_supply, 1_000);
+        assert_eq!((pool.owed_usdc_fees, pool.owed_wsol_fees), (11, 13));
+        assert!(pool.legacy_seeds);
+    }
+
+    #[test]
+    fn fee_tier_layout_reads_the_tier_before_the_fee_mode() {
+        let mut data = Vec::new();
+        base_fields(&mut data);
+        flash_loan_fields(&mut data);
+        (CurveType::Weighted, 5_000u64)
+            .serialize(&mut data)
+            .unwrap();
+        5u16.serialize(&mut data).unwrap();
+        claimable_fee_fields(&mut data);
+        protocol_fee_fields(&mut data);
+        (50u64, 60u64).serialize(&mut data).unwrap();
+
+        let legacy = LegacyPool::decode(&data).unwrap();
+        assert_eq!(legacy.fee_tier_bps, Some(5));
+        assert_eq!(legacy.fee_mode, FeeMode::Claimable);
+        assert_eq!(legacy.unclaimed_wsol_fees, 13);
+
+        //stored reserves win over the vault balances
+        let pool = legacy.upgrade((100, 200), 1_000, key(9)).unwrap();
+        assert_eq!((pool.usdc_reserve, pool.wsol_reserve), (50, 60));
+        assert_eq!(pool.fee_tier_bps, 5);
+        assert!(!pool.legacy_seeds);
+    }
+
+    #[test]
+    fn unknown_sizes_are_rejected() {
+        assert!(LegacyPool::decode(&[0; 100]).is_err());
+    }
+
+    #[test]
+    fn rewrite_reallocs_an_old_layout_account() {
+        let pool_key = key(42);
+        let old = base_layout();
+        let mut input = runtime_input(&pool_key, &old);
+        let (_, accounts, _) = unsafe { entrypoint::deserialize(input.as_mut_ptr() as *mut u8) };
+        let pool_info = &accounts[0];
+        assert_eq!(pool_info.data_len(), old.len());
+
+        let legacy = LegacyPool::decode(&pool_info.try_borrow_data().unwrap()[8..]).unwrap();
+        let pool = legacy.upgrade((100, 200), 0, key(9)).unwrap();
+        rewrite_pool(pool_info, &pool).unwrap();
+
+        assert_eq!(pool_info.data_len(), 8 + LpPoolAccountShape::INIT_SPACE);
+        let data = pool_info.try_borrow_data().unwrap();
+        assert_eq!(data[..old.len()], old[..]);
+        let migrated = LpPoolAccountShape::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(migrated.lp_token_mint, key(5));
+        assert_eq!(migrated.bump, 254);
+        assert_eq!(migrated.usdc_reserve, 100);
+        assert_eq!(migrated.version, POOL_VERSION);
+        assert!(migrated.legacy_seeds);
+    }
+}
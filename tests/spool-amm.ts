@@ -1,13 +1,19 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { SpoolAmm } from "../target/types/spool_amm";
+import { FlashSwapCallback } from "../target/types/flash_swap_callback";
 import { LAMPORTS_PER_SOL, PublicKey, SystemProgram, Transaction, Keypair } from "@solana/web3.js";
 import {
+  createMint,
+  createAssociatedTokenAccount,
+  mintTo,
+  transfer,
   getMint,
   TOKEN_PROGRAM_ID,
   getAccount,
   createAssociatedTokenAccountInstruction,
   getAssociatedTokenAddress,
+  getAssociatedTokenAddressSync,
   createSyncNativeInstruction,
   getOrCreateAssociatedTokenAccount
 } from "@solana/spl-token"
@@ -21,6 +27,7 @@ describe("spool-amm", () => {
   anchor.setProvider(provider);
 
   const program = anchor.workspace.spoolAmm as Program<SpoolAmm>;
+  const callbackProgram = anchor.workspace.flashSwapCallback as Program<FlashSwapCallback>;
 
   // Keypair for user
   const user_keypair = anchor.web3.Keypair.fromSecretKey(base58.decode(""));
@@ -32,18 +39,28 @@ describe("spool-amm", () => {
   const usdcMintPubkey = new PublicKey(usdc_mint_address)
   const wsolMintPubkey = new PublicKey(sol_mint_address)
 
-  // LP mint keypair - generated for initialization
-  const lpMintKeypair = Keypair.generate();
-
   // Correct seeds matching lib.rs (v3 suffixes)
-  const usdc_vault_seed = [Buffer.from("usdc_vault_v3"), usdcMintPubkey.toBuffer()];
-  const wsol_vault_seed = [Buffer.from("sol_vault_v3"), wsolMintPubkey.toBuffer()];
-  const pool_state_seed = [Buffer.from("pool_state_v3"), usdcMintPubkey.toBuffer(), wsolMintPubkey.toBuffer()];
+  // Pools are keyed by the pair and the fee tier in basis points
+  const FEE_TIER_BPS = 30;
+  const feeTierSeed = (bps: number) => new BN(bps).toArrayLike(Buffer, "le", 2);
+  const pool_state_seed = [Buffer.from("pool_state_v3"), usdcMintPubkey.toBuffer(), wsolMintPubkey.toBuffer(), feeTierSeed(FEE_TIER_BPS)];
 
   // Find PDAs
-  const [usdcVaultPda] = PublicKey.findProgramAddressSync(usdc_vault_seed, program.programId);
-  const [wsolVaultPda] = PublicKey.findProgramAddressSync(wsol_vault_seed, program.programId);
   const [poolStatePda] = PublicKey.findProgramAddressSync(pool_state_seed, program.programId);
+  const [usdcVaultPda] = PublicKey.findProgramAddressSync([Buffer.from("usdc_vault_v3"), poolStatePda.toBuffer()], program.programId);
+  const [wsolVaultPda] = PublicKey.findProgramAddressSync([Buffer.from("sol_vault_v3"), poolStatePda.toBuffer()], program.programId);
+  const [lpMintPda] = PublicKey.findProgramAddressSync([Buffer.from("lp_mint_v3"), poolStatePda.toBuffer()], program.programId);
+  const [ammConfigPda] = PublicKey.findProgramAddressSync([Buffer.from("amm_config_v3")], program.programId);
+
+  // Registry page the next pool is added to
+  const nextRegistryPage = async () => {
+    const config = await program.account.ammConfigAccountShape.fetch(ammConfigPda);
+    const page = config.poolCount.divn(64);
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_registry_v3"), page.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+  };
 
   // Helper function to get or create ATA
   const getOrCreateATA = async (mint: PublicKey, owner: PublicKey, isWrappedSol = false, solAmount = 0) => {
@@ -80,6 +97,122 @@ describe("spool-amm", () => {
     return ata;
   };
 
+  // Fresh wallet with SOL for fees and rent
+  const newWallet = async () => {
+    const wallet = Keypair.generate();
+    const airdrop = await provider.connection.requestAirdrop(wallet.publicKey, 2 * LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdrop, "confirmed");
+    return wallet;
+  };
+
+  // Fresh pair of mints, 6 and 9 decimals like USDC and SOL unless given
+  const newPair = async (authority: Keypair, decimalsB = 9) => {
+    const mintA = await createMint(provider.connection, authority, authority.publicKey, null, 6);
+    const mintB = await createMint(provider.connection, authority, authority.publicKey, null, decimalsB);
+    return { mintA, mintB };
+  };
+
+  // ATAs of the owner for both mints of the pair, funded by the mint authority
+  const fundPair = async (authority: Keypair, { mintA, mintB }: { mintA: PublicKey; mintB: PublicKey }, owner: Keypair, amountA: number, amountB: number) => {
+    const accountA = await createAssociatedTokenAccount(provider.connection, owner, mintA, owner.publicKey);
+    const accountB = await createAssociatedTokenAccount(provider.connection, owner, mintB, owner.publicKey);
+    await mintTo(provider.connection, authority, mintA, accountA, authority, amountA);
+    await mintTo(provider.connection, authority, mintB, accountB, authority, amountB);
+    return { accountA, accountB };
+  };
+
+  // Pool accounts of a pair and fee tier
+  const poolAccountsFor = (mintA: PublicKey, mintB: PublicKey, feeTierBps = FEE_TIER_BPS) => {
+    const [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_state_v3"), mintA.toBuffer(), mintB.toBuffer(), feeTierSeed(feeTierBps)],
+      program.programId
+    );
+    const [usdcVault] = PublicKey.findProgramAddressSync([Buffer.from("usdc_vault_v3"), pool.toBuffer()], program.programId);
+    const [wsolVault] = PublicKey.findProgramAddressSync([Buffer.from("sol_vault_v3"), pool.toBuffer()], program.programId);
+    const [lpMint] = PublicKey.findProgramAddressSync([Buffer.from("lp_mint_v3"), pool.toBuffer()], program.programId);
+    const [lockedLp] = PublicKey.findProgramAddressSync([Buffer.from("locked_lp_v3"), pool.toBuffer()], program.programId);
+    return { pool, usdcVault, wsolVault, lpMint, lockedLp };
+  };
+
+  // Wallet with a fresh pair, both mints funded to its ATAs
+  const newPairWallet = async (amountA: number, amountB: number, decimalsB = 9) => {
+    const wallet = await newWallet();
+    const pair = await newPair(wallet, decimalsB);
+    const accounts = await fundPair(wallet, pair, wallet, amountA, amountB);
+    return { wallet, ...pair, ...accounts };
+  };
+
+  // Empty constant product pool created by the wallet
+  const initializePool = async (wallet: Keypair, mintA: PublicKey, mintB: PublicKey, feeMode: Parameters<typeof program.methods.initialize>[2] = { compound: {} }, feeTierBps = FEE_TIER_BPS) =>
+    program.methods.initialize({ constantProduct: {} }, new BN(0), feeMode, feeTierBps)
+      .accounts({
+        signer: wallet.publicKey,
+        payer: wallet.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        registryPage: await nextRegistryPage(),
+        feeReceiver: provider.wallet.publicKey,
+      })
+      .signers([wallet])
+      .rpc({ commitment: "confirmed" });
+
+  // Constant product pool seeded by the wallet of newPairWallet
+  const initializePoolWithLiquidity = async (
+    { wallet, mintA, mintB, accountA, accountB }: { wallet: Keypair; mintA: PublicKey; mintB: PublicKey; accountA: PublicKey; accountB: PublicKey },
+    usdcAmount: number,
+    wsolAmount: number,
+    feeTierBps = FEE_TIER_BPS,
+    registryPage?: PublicKey
+  ) =>
+    program.methods.initializeWithLiquidity({ constantProduct: {} }, new BN(0), feeTierBps, new BN(usdcAmount), new BN(wsolAmount))
+      .accountsPartial({
+        signer: wallet.publicKey,
+        payer: wallet.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        userUsdcAccount: accountA,
+        userWsolAccount: accountB,
+        poolStateaccount: poolAccountsFor(mintA, mintB, feeTierBps).pool,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        registryPage: registryPage ?? (await nextRegistryPage()),
+        feeReceiver: provider.wallet.publicKey,
+      })
+      .signers([wallet])
+      .rpc({ commitment: "confirmed" });
+
+  it("Initialize the amm config", async () => {
+    const accountInfo = await provider.connection.getAccountInfo(ammConfigPda);
+    if (accountInfo !== null) {
+      console.log("⚠️ Config already initialized. Skipping init.");
+      return;
+    }
+
+    // The provider wallet deployed the program, it is the upgrade authority
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods.initializeConfig({
+        admin: provider.wallet.publicKey,
+        feeReceiver: provider.wallet.publicKey,
+        poolCreationFee: new BN(LAMPORTS_PER_SOL / 100),
+        // Every CurveType and concentrated liquidity
+        allowedCurves: 0b111111,
+        feeTiers: [1, 5, 30, 100],
+      })
+      .accountsPartial({
+        signer: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        programData: programData,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const config = await program.account.ammConfigAccountShape.fetch(ammConfigPda);
+    assert.deepEqual(config.feeTiers, [1, 5, 30, 100]);
+    assert.equal(config.poolCount.toString(), "0");
+  });
+
   it("Initialize pool", async () => {
     // Check if pool already exists
     const accountInfo = await provider.connection.getAccountInfo(poolStatePda);
@@ -92,21 +225,28 @@ describe("spool-amm", () => {
     console.log("Pool State PDA:", poolStatePda.toString());
     console.log("USDC Vault PDA:", usdcVaultPda.toString());
     console.log("WSOL Vault PDA:", wsolVaultPda.toString());
-    console.log("LP Mint:", lpMintKeypair.publicKey.toString());
+    console.log("LP Mint PDA:", lpMintPda.toString());
 
-    const tx = await program.methods.initialize()
+    // Constant product curve, it takes no parameter
+    const registryPage = await nextRegistryPage();
+    const tx = await program.methods.initialize({ constantProduct: {} }, new BN(0), { compound: {} }, FEE_TIER_BPS)
       .accounts({
         signer: user_keypair.publicKey,
+        payer: user_keypair.publicKey,
         usdcMint: usdcMintPubkey,
         wsolMint: wsolMintPubkey,
-        mint: lpMintKeypair.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        registryPage: registryPage,
+        feeReceiver: provider.wallet.publicKey,
       })
-      .signers([user_keypair, lpMintKeypair])
+      .signers([user_keypair])
       .rpc({ commitment: "confirmed" });
 
     console.log("✅ Initialize tx:", tx);
 
+    const registry = await program.account.poolRegistryPageAccountShape.fetch(registryPage);
+    assert.ok(registry.pools.some((pool) => pool.equals(poolStatePda)), "Pool should be registered");
+
     // Verify accounts were created
     const usdcVaultAccount = await getAccount(provider.connection, usdcVaultPda, "confirmed", TOKEN_PROGRAM_ID);
     const wsolVaultAccount = await getAccount(provider.connection, wsolVaultPda, "confirmed", TOKEN_PROGRAM_ID);
@@ -129,9 +269,8 @@ describe("spool-amm", () => {
     const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
     const userWsolAccount = await getOrCreateATA(wsolMintPubkey, user_keypair.publicKey, true, 1);
 
-    // LP ATA seed (v3 suffix)
-    const lpAtaSeed = [Buffer.from("lptokenata_v3"), user_keypair.publicKey.toBuffer()];
-    const [lpAtaPda] = PublicKey.findProgramAddressSync(lpAtaSeed, program.programId);
+    // LP ATA
+    const lpAtaPda = getAssociatedTokenAddressSync(lpMintPubkey, user_keypair.publicKey);
 
     // Amounts to provide
     const usdcAmount = new BN(100).mul(new BN(10).pow(new BN(6))); // 100 USDC (6 decimals)
@@ -145,6 +284,8 @@ describe("spool-amm", () => {
       const tx = await program.methods.providelp(wsolAmount, usdcAmount)
         .accountsPartial({
           signer: user_keypair.publicKey,
+          beneficiary: user_keypair.publicKey,
+          payer: user_keypair.publicKey,
           usdcMint: usdcMintPubkey,
           wsolMint: wsolMintPubkey,
           userUsdcAccount: userUsdcAccount,
@@ -171,6 +312,47 @@ describe("spool-amm", () => {
     }
   });
 
+  it("Provide single sided liquidity", async () => {
+    const poolStateAccount = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    const lpMintPubkey = poolStateAccount.lpTokenMint;
+
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userWsolAccount = await getAssociatedTokenAddress(wsolMintPubkey, user_keypair.publicKey);
+    const lpAtaPda = getAssociatedTokenAddressSync(lpMintPubkey, user_keypair.publicKey);
+
+    const lpBefore = await getAccount(provider.connection, lpAtaPda, "confirmed");
+    const wsolBefore = await getAccount(provider.connection, userWsolAccount, "confirmed");
+
+    // Only USDC goes in, half of it is swapped inside the pool
+    const usdcAmount = new BN(10).mul(new BN(10).pow(new BN(6)));
+
+    const tx = await program.methods.provideSingleSided(usdcMintPubkey, usdcAmount, new BN(1))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        beneficiary: user_keypair.publicKey,
+        payer: user_keypair.publicKey,
+        usdcMint: usdcMintPubkey,
+        wsolMint: wsolMintPubkey,
+        userUsdcAccount: userUsdcAccount,
+        userWsolAccount: userWsolAccount,
+        usdcVaultAccount: usdcVaultPda,
+        wsolVaultAccount: wsolVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        lptokenmint: lpMintPubkey,
+        lpAta: lpAtaPda,
+        mintAuthority: poolStatePda,
+      })
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("✅ Single sided LP tx:", tx);
+
+    const lpAfter = await getAccount(provider.connection, lpAtaPda, "confirmed");
+    const wsolAfter = await getAccount(provider.connection, userWsolAccount, "confirmed");
+    assert.ok(lpAfter.amount > lpBefore.amount, "Should have received LP tokens");
+    assert.equal(wsolAfter.amount, wsolBefore.amount, "WSOL balance should be untouched");
+  });
+
   it("Swap USDC to wSOL", async () => {
     // Get user token accounts
     const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
@@ -214,6 +396,12 @@ describe("spool-amm", () => {
       console.log("Final wSOL balance:", finalWsolBalance.amount.toString());
 
       assert.ok(finalWsolBalance.amount > 0, "Should have received wSOL");
+      // The fee is taken from the swap amount, not on top of what the curve sees
+      assert.equal(
+        (initialUsdcBalance.amount - finalUsdcBalance.amount).toString(),
+        amountToSwap.toString(),
+        "Should have paid the whole swap amount"
+      );
     } catch (error) {
       console.log("❌ Failed to swap:", error);
       throw error;
@@ -253,6 +441,1199 @@ describe("spool-amm", () => {
     }
   });
 
+  it("Swap output delivered to another wallet", async () => {
+    const recipient = Keypair.generate();
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const recipientWsolAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection, user_keypair, wsolMintPubkey, recipient.publicKey
+    );
+
+    const tx = await program.methods.swap(new BN(1_000_000))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        inputMint: usdcMintPubkey,
+        outputMint: wsolMintPubkey,
+        poolStateaccount: poolStatePda,
+        inputVaultAccount: usdcVaultPda,
+        outputVaultAccount: wsolVaultPda,
+        userInputAccount: userUsdcAccount,
+        userOutputAccount: recipientWsolAccount.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    const received = await getAccount(provider.connection, recipientWsolAccount.address, "confirmed");
+    assert.ok(received.amount > BigInt(0), "Recipient should have received wSOL");
+
+    // The swap event names the recipient, not the signer
+    const txInfo = await provider.connection.getTransaction(tx, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+    const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+    const events = [...parser.parseLogs(txInfo.meta.logMessages)].filter((event) => event.name === "swapEvent");
+    assert.equal(events.length, 1);
+    assert.ok(events[0].data.recipient.equals(recipient.publicKey));
+    assert.ok(events[0].data.recipientAccount.equals(recipientWsolAccount.address));
+    assert.equal(events[0].data.amountOut.toString(), received.amount.toString());
+
+    // An account of the wrong mint is still rejected
+    try {
+      await program.methods.swap(new BN(1_000_000))
+        .accountsPartial({
+          signer: user_keypair.publicKey,
+          inputMint: usdcMintPubkey,
+          outputMint: wsolMintPubkey,
+          poolStateaccount: poolStatePda,
+          inputVaultAccount: usdcVaultPda,
+          outputVaultAccount: wsolVaultPda,
+          userInputAccount: userUsdcAccount,
+          userOutputAccount: userUsdcAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user_keypair])
+        .rpc({ commitment: "confirmed" });
+      assert.fail("Output account of the wrong mint should fail");
+    } catch (error) {
+      assert.include(error.toString(), "ConstraintTokenMint");
+    }
+  });
+
+  it("Route swap USDC to wSOL through the pool", async () => {
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userWsolAccount = await getAssociatedTokenAddress(wsolMintPubkey, user_keypair.publicKey);
+
+    const wsolBefore = await getAccount(provider.connection, userWsolAccount, "confirmed");
+    const amountIn = new BN(1).mul(new BN(10).pow(new BN(6))); // 1 USDC
+
+    // Each hop: pool state, input vault, output vault, output mint
+    const tx = await program.methods.routeSwap(amountIn, new BN(1))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        inputMint: usdcMintPubkey,
+        outputMint: wsolMintPubkey,
+        userInputAccount: userUsdcAccount,
+        userOutputAccount: userWsolAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: poolStatePda, isSigner: false, isWritable: true },
+        { pubkey: usdcVaultPda, isSigner: false, isWritable: true },
+        { pubkey: wsolVaultPda, isSigner: false, isWritable: true },
+        { pubkey: wsolMintPubkey, isSigner: false, isWritable: false },
+      ])
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("✅ Route swap tx:", tx);
+
+    const wsolAfter = await getAccount(provider.connection, userWsolAccount, "confirmed");
+    assert.ok(wsolAfter.amount > wsolBefore.amount, "Should have received wSOL");
+
+    // An unreachable minimum reverts the whole route
+    try {
+      await program.methods.routeSwap(amountIn, new BN("18446744073709551615"))
+        .accountsPartial({
+          signer: user_keypair.publicKey,
+          inputMint: usdcMintPubkey,
+          outputMint: wsolMintPubkey,
+          userInputAccount: userUsdcAccount,
+          userOutputAccount: userWsolAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: poolStatePda, isSigner: false, isWritable: true },
+          { pubkey: usdcVaultPda, isSigner: false, isWritable: true },
+          { pubkey: wsolVaultPda, isSigner: false, isWritable: true },
+          { pubkey: wsolMintPubkey, isSigner: false, isWritable: false },
+        ])
+        .signers([user_keypair])
+        .rpc({ commitment: "confirmed" });
+      assert.fail("Route swap below the minimum should fail");
+    } catch (error) {
+      assert.include(error.toString(), "SlippageExceeded");
+    }
+  });
+
+  it("Provide liquidity on behalf of another owner", async () => {
+    const poolStateAccount = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    const lpMintPubkey = poolStateAccount.lpTokenMint;
+
+    // The lp goes to a wallet that neither funds the deposit nor pays the rent
+    const beneficiary = Keypair.generate();
+    const beneficiaryLpAta = getAssociatedTokenAddressSync(lpMintPubkey, beneficiary.publicKey);
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userWsolAccount = await getAssociatedTokenAddress(wsolMintPubkey, user_keypair.publicKey);
+    const userLpAta = getAssociatedTokenAddressSync(lpMintPubkey, user_keypair.publicKey);
+    const userLpBefore = await getAccount(provider.connection, userLpAta, "confirmed");
+
+    await program.methods.providelp(new BN(100_000_000), new BN(10_000_000))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        beneficiary: beneficiary.publicKey,
+        payer: provider.wallet.publicKey,
+        usdcMint: usdcMintPubkey,
+        wsolMint: wsolMintPubkey,
+        userUsdcAccount: userUsdcAccount,
+        userWsolAccount: userWsolAccount,
+        usdcVaultAccount: usdcVaultPda,
+        wsolVaultAccount: wsolVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        lptokenmint: lpMintPubkey,
+        lpAta: beneficiaryLpAta,
+        mintAuthority: poolStatePda,
+      })
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    const beneficiaryLp = await getAccount(provider.connection, beneficiaryLpAta, "confirmed");
+    assert.ok(beneficiaryLp.amount > BigInt(0), "Beneficiary should have received lp");
+    assert.ok(beneficiaryLp.owner.equals(beneficiary.publicKey));
+    const userLpAfter = await getAccount(provider.connection, userLpAta, "confirmed");
+    assert.equal(userLpAfter.amount, userLpBefore.amount, "Depositor lp should be untouched");
+  });
+
+  it("Provide liquidity with native SOL", async () => {
+    const poolStateAccount = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    const lpMintPubkey = poolStateAccount.lpTokenMint;
+
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const lpAtaPda = getAssociatedTokenAddressSync(lpMintPubkey, user_keypair.publicKey);
+
+    const lpBefore = await getAccount(provider.connection, lpAtaPda, "confirmed");
+    const wsolVaultBefore = await getAccount(provider.connection, wsolVaultPda, "confirmed");
+
+    // No WSOL account needed, lamports are wrapped into the vault
+    const lamports = new BN(0.1 * LAMPORTS_PER_SOL);
+    const usdcAmount = new BN(10).mul(new BN(10).pow(new BN(6)));
+
+    const tx = await program.methods.providelpNative(lamports, usdcAmount)
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        payer: user_keypair.publicKey,
+        usdcMint: usdcMintPubkey,
+        wsolMint: wsolMintPubkey,
+        userUsdcAccount: userUsdcAccount,
+        usdcVaultAccount: usdcVaultPda,
+        wsolVaultAccount: wsolVaultPda,
+        lptokenmint: lpMintPubkey,
+        lpAta: lpAtaPda,
+        mintAuthority: poolStatePda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("✅ Provide LP native tx:", tx);
+
+    const lpAfter = await getAccount(provider.connection, lpAtaPda, "confirmed");
+    const wsolVaultAfter = await getAccount(provider.connection, wsolVaultPda, "confirmed");
+    assert.ok(lpAfter.amount > lpBefore.amount, "Should have received LP tokens");
+    assert.equal(
+      (wsolVaultAfter.amount - wsolVaultBefore.amount).toString(),
+      lamports.toString(),
+      "Vault should hold the wrapped lamports"
+    );
+  });
+
+  it("Swap native SOL to USDC and back", async () => {
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const [tempWsolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("temp_wsol_v3"), user_keypair.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const usdcBefore = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+
+    const solIn = await program.methods.swapSolToToken(new BN(0.05 * LAMPORTS_PER_SOL))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        usdcMint: usdcMintPubkey,
+        userOutputAccount: userUsdcAccount,
+        inputVaultAccount: wsolVaultPda,
+        outputVaultAccount: usdcVaultPda,
+        poolStateaccount: poolStatePda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+    console.log("✅ SOL -> USDC tx:", solIn);
+
+    const usdcMid = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+    assert.ok(usdcMid.amount > usdcBefore.amount, "Should have received USDC");
+
+    const lamportsBefore = await provider.connection.getBalance(user_keypair.publicKey, "confirmed");
+    const solOut = await program.methods.swapTokenToSol(new BN(5).mul(new BN(10).pow(new BN(6))))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        payer: user_keypair.publicKey,
+        usdcMint: usdcMintPubkey,
+        wsolMint: wsolMintPubkey,
+        userInputAccount: userUsdcAccount,
+        inputVaultAccount: usdcVaultPda,
+        outputVaultAccount: wsolVaultPda,
+        tempWsolAccount: tempWsolPda,
+        poolStateaccount: poolStatePda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+    console.log("✅ USDC -> SOL tx:", solOut);
+
+    const lamportsAfter = await provider.connection.getBalance(user_keypair.publicKey, "confirmed");
+    assert.ok(lamportsAfter > lamportsBefore, "Should have received lamports");
+    assert.isNull(
+      await provider.connection.getAccountInfo(tempWsolPda, "confirmed"),
+      "Temporary WSOL account should be closed"
+    );
+  });
+
+  it("A relayer pays the rent for a wallet holding no SOL", async () => {
+    // The user only holds USDC, the provider wallet pays the fee and the rent
+    const user = Keypair.generate();
+    const sourceUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userUsdcAccount = await createAssociatedTokenAccount(provider.connection, user_keypair, usdcMintPubkey, user.publicKey);
+    await transfer(provider.connection, user_keypair, sourceUsdcAccount, userUsdcAccount, user_keypair, 5_000_000);
+    const [tempWsolPda] = PublicKey.findProgramAddressSync([Buffer.from("temp_wsol_v3"), user.publicKey.toBuffer()], program.programId);
+
+    const poolBefore = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    await program.methods.swapTokenToSol(new BN(5_000_000))
+      .accountsPartial({
+        signer: user.publicKey,
+        payer: provider.wallet.publicKey,
+        usdcMint: usdcMintPubkey,
+        wsolMint: wsolMintPubkey,
+        userInputAccount: userUsdcAccount,
+        inputVaultAccount: usdcVaultPda,
+        outputVaultAccount: wsolVaultPda,
+        tempWsolAccount: tempWsolPda,
+        poolStateaccount: poolStatePda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc({ commitment: "confirmed" });
+    const poolAfter = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+
+    // The temporary account rent went back to the relayer, the user keeps exactly the output
+    const solOut = poolBefore.wsolReserve.sub(poolAfter.wsolReserve);
+    const lamports = await provider.connection.getBalance(user.publicKey, "confirmed");
+    assert.equal(lamports.toString(), solOut.toString());
+  });
+
+  it("Flash loan from the USDC vault", async () => {
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const vaultBefore = await getAccount(provider.connection, usdcVaultPda, "confirmed");
+
+    const loanAmount = new BN(1).mul(new BN(10).pow(new BN(6))); // 1 USDC
+
+    const loanIx = await program.methods.flashLoan(loanAmount)
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        poolStateAccount: poolStatePda,
+        mint: usdcMintPubkey,
+        vaultAccount: usdcVaultPda,
+        borrowerAccount: userUsdcAccount,
+        instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .instruction();
+    const repayIx = await program.methods.flashRepay()
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        poolStateAccount: poolStatePda,
+        mint: usdcMintPubkey,
+        vaultAccount: usdcVaultPda,
+        repayerAccount: userUsdcAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .instruction();
+
+    // A loan without a repay in the same transaction is rejected
+    try {
+      await provider.sendAndConfirm(new Transaction().add(loanIx), [user_keypair]);
+      assert.fail("Flash loan without repay should fail");
+    } catch (error) {
+      assert.include(error.toString(), "MissingRepay");
+    }
+
+    const tx = await provider.sendAndConfirm(new Transaction().add(loanIx, repayIx), [user_keypair]);
+    console.log("✅ Flash loan tx:", tx);
+
+    const vaultAfter = await getAccount(provider.connection, usdcVaultPda, "confirmed");
+    const poolStateAccount = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    assert.ok(vaultAfter.amount > vaultBefore.amount, "Vault should have earned the loan fee");
+    assert.isFalse(poolStateAccount.locked, "Pool should be unlocked after repay");
+  });
+
+  // Flash swap USDC out of the pool, repaid by the callback program in the given mode
+  const flashSwap = async (repayMode: number) => {
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userWsolAccount = await getAssociatedTokenAddress(wsolMintPubkey, user_keypair.publicKey);
+
+    const usdcVaultBefore = await getAccount(provider.connection, usdcVaultPda, "confirmed");
+    const wsolVaultBefore = await getAccount(provider.connection, wsolVaultPda, "confirmed");
+
+    const amountOut = new BN(1).mul(new BN(10).pow(new BN(6))); // 1 USDC
+
+    const tx = await program.methods.flashSwap(amountOut, Buffer.from([repayMode]))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        poolStateAccount: poolStatePda,
+        outputMint: usdcMintPubkey,
+        inputVaultAccount: wsolVaultPda,
+        outputVaultAccount: usdcVaultPda,
+        recipientAccount: userUsdcAccount,
+        callbackProgram: callbackProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: user_keypair.publicKey, isSigner: true, isWritable: false },
+        { pubkey: wsolMintPubkey, isSigner: false, isWritable: false },
+        { pubkey: usdcMintPubkey, isSigner: false, isWritable: false },
+        { pubkey: userWsolAccount, isSigner: false, isWritable: true },
+        { pubkey: userUsdcAccount, isSigner: false, isWritable: true },
+        { pubkey: wsolVaultPda, isSigner: false, isWritable: true },
+        { pubkey: usdcVaultPda, isSigner: false, isWritable: true },
+        { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      ])
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    const usdcVaultAfter = await getAccount(provider.connection, usdcVaultPda, "confirmed");
+    const wsolVaultAfter = await getAccount(provider.connection, wsolVaultPda, "confirmed");
+    const before = BigInt(usdcVaultBefore.amount) * BigInt(wsolVaultBefore.amount);
+    const after = BigInt(usdcVaultAfter.amount) * BigInt(wsolVaultAfter.amount);
+    assert.ok(after > before, "Pool product should grow by the fee");
+    return tx;
+  };
+
+  it("Flash swap repaid in the input token", async () => {
+    const tx = await flashSwap(0);
+    console.log("✅ Flash swap (input repay) tx:", tx);
+  });
+
+  it("Flash swap repaid in the output token", async () => {
+    const tx = await flashSwap(1);
+    console.log("✅ Flash swap (output repay) tx:", tx);
+  });
+
+  it("Concentrated liquidity position, swap and fee collection", async () => {
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userWsolAccount = await getOrCreateATA(wsolMintPubkey, user_keypair.publicKey, true, 0.1);
+
+    // Tick spacing 64, 0.3% fee, starting at price 1
+    const tickSpacing = 64;
+    const tickSpacingSeed = Buffer.alloc(2);
+    tickSpacingSeed.writeUInt16LE(tickSpacing);
+    const [clmmPoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("clmm_pool_v3"), usdcMintPubkey.toBuffer(), wsolMintPubkey.toBuffer(), tickSpacingSeed],
+      program.programId
+    );
+    const [clmmUsdcVault] = PublicKey.findProgramAddressSync([Buffer.from("clmm_usdc_vault_v3"), clmmPoolPda.toBuffer()], program.programId);
+    const [clmmWsolVault] = PublicKey.findProgramAddressSync([Buffer.from("clmm_wsol_vault_v3"), clmmPoolPda.toBuffer()], program.programId);
+
+    const i32Seed = (value: number) => {
+      const buffer = Buffer.alloc(4);
+      buffer.writeInt32LE(value);
+      return buffer;
+    };
+
+    if ((await provider.connection.getAccountInfo(clmmPoolPda)) === null) {
+      await program.methods.initializeClmmPool(tickSpacing, 30, new BN(2).pow(new BN(64)))
+        .accounts({
+          payer: user_keypair.publicKey,
+          usdcMint: usdcMintPubkey,
+          wsolMint: wsolMintPubkey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          registryPage: await nextRegistryPage(),
+          feeReceiver: provider.wallet.publicKey,
+        })
+        .signers([user_keypair])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    // Arrays hold 16 ticks, the price sits in the array starting at 0
+    const tickArray = async (start: number) => {
+      const [pda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tick_array_v3"), clmmPoolPda.toBuffer(), i32Seed(start)],
+        program.programId
+      );
+      if ((await provider.connection.getAccountInfo(pda)) === null) {
+        await program.methods.initializeTickArray(start)
+          .accounts({ payer: user_keypair.publicKey, clmmPool: clmmPoolPda })
+          .signers([user_keypair])
+          .rpc({ commitment: "confirmed" });
+      }
+      return pda;
+    };
+    const lowerArray = await tickArray(-1024);
+    const upperArray = await tickArray(0);
+
+    // Position from tick -512 to 512
+    const [positionPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("position_v3"), clmmPoolPda.toBuffer(), user_keypair.publicKey.toBuffer(), i32Seed(-512), i32Seed(512)],
+      program.programId
+    );
+    if ((await provider.connection.getAccountInfo(positionPda)) === null) {
+      await program.methods.openPosition(-512, 512)
+        .accounts({ owner: user_keypair.publicKey, payer: user_keypair.publicKey, clmmPool: clmmPoolPda })
+        .signers([user_keypair])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    const modifyAccounts = {
+      owner: user_keypair.publicKey,
+      usdcMint: usdcMintPubkey,
+      wsolMint: wsolMintPubkey,
+      clmmPool: clmmPoolPda,
+      position: positionPda,
+      tickArrayLower: lowerArray,
+      tickArrayUpper: upperArray,
+      usdcVault: clmmUsdcVault,
+      wsolVault: clmmWsolVault,
+      userUsdcAccount: userUsdcAccount,
+      userWsolAccount: userWsolAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    const liquidity = new BN(100_000_000);
+    const amountMax = new BN(10).pow(new BN(9));
+    await program.methods.increaseLiquidity(liquidity, amountMax, amountMax)
+      .accountsPartial(modifyAccounts)
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    // USDC in moves the price down, through the array at 0 then the one below
+    const wsolBefore = await getAccount(provider.connection, userWsolAccount, "confirmed");
+    await program.methods.swapClmm(new BN(100_000), new BN(1))
+      .accountsPartial({
+        signer: user_keypair.publicKey,
+        clmmPool: clmmPoolPda,
+        inputMint: usdcMintPubkey,
+        outputMint: wsolMintPubkey,
+        inputVault: clmmUsdcVault,
+        outputVault: clmmWsolVault,
+        userInputAccount: userUsdcAccount,
+        userOutputAccount: userWsolAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: upperArray, isSigner: false, isWritable: true },
+        { pubkey: lowerArray, isSigner: false, isWritable: true },
+      ])
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+    const wsolAfter = await getAccount(provider.connection, userWsolAccount, "confirmed");
+    assert.ok(wsolAfter.amount > wsolBefore.amount, "Should have received wSOL");
+
+    // The whole swap fee belongs to the only position in range
+    const usdcBefore = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+    await program.methods.collectPositionFees()
+      .accountsPartial(modifyAccounts)
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+    const usdcAfter = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+    assert.ok(usdcAfter.amount > usdcBefore.amount, "Should have collected USDC fees");
+
+    await program.methods.decreaseLiquidity(liquidity, new BN(0), new BN(0))
+      .accountsPartial(modifyAccounts)
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+    const position = await program.account.positionAccountShape.fetch(positionPda);
+    assert.equal(position.liquidity.toString(), "0");
+  });
+
+  it("Claim swap fees from a claimable pool", async () => {
+    // Fresh pair and lp, the lp account seed is per signer
+    const { wallet: lp, mintA, mintB, accountA, accountB } = await newPairWallet(1_000_000_000_000, 1_000_000_000_000);
+    const { pool, usdcVault: vaultA, wsolVault: vaultB, lpMint } = poolAccountsFor(mintA, mintB);
+    const [checkpoint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("fee_checkpoint_v3"), pool.toBuffer(), lp.publicKey.toBuffer()],
+      program.programId
+    );
+    const lpAta = getAssociatedTokenAddressSync(lpMint, lp.publicKey);
+
+    await initializePool(lp, mintA, mintB, { claimable: {} });
+
+    // The checkpoint has to exist before the first deposit
+    await program.methods.openFeeCheckpoint()
+      .accountsPartial({
+        signer: lp.publicKey,
+        payer: lp.publicKey,
+        poolStateAccount: pool,
+        lpTokenMint: lpMint,
+        lpAta: lpAta,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([lp])
+      .rpc({ commitment: "confirmed" });
+
+    await program.methods.providelp(new BN(100_000_000_000), new BN(100_000_000))
+      .accountsPartial({
+        signer: lp.publicKey,
+        beneficiary: lp.publicKey,
+        payer: lp.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        userUsdcAccount: accountA,
+        userWsolAccount: accountB,
+        usdcVaultAccount: vaultA,
+        wsolVaultAccount: vaultB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        lptokenmint: lpMint,
+        lpAta: lpAta,
+        mintAuthority: pool,
+        feeCheckpoint: checkpoint,
+      })
+      .signers([lp])
+      .rpc({ commitment: "confirmed" });
+
+    // The lp account is frozen while the checkpoint tracks it
+    const lpAccount = await getAccount(provider.connection, lpAta, "confirmed");
+    assert.ok(lpAccount.isFrozen, "Checkpointed lp account should be frozen");
+
+    const amountIn = new BN(10_000_000);
+    await program.methods.swap(amountIn)
+      .accountsPartial({
+        signer: lp.publicKey,
+        inputMint: mintA,
+        outputMint: mintB,
+        poolStateaccount: pool,
+        inputVaultAccount: vaultA,
+        outputVaultAccount: vaultB,
+        userInputAccount: accountA,
+        userOutputAccount: accountB,
+        lpTokenMint: lpMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([lp])
+      .rpc({ commitment: "confirmed" });
+
+    // The 0.3% fee tier charges 30_000, only the checkpointed lp share of it leaves the
+    // reserves, the locked minimum liquidity's share compounds
+    const poolState = await program.account.lpPoolAccountShape.fetch(pool);
+    const lpSupply = (await getMint(provider.connection, lpMint, "confirmed")).supply;
+    assert.equal(poolState.checkpointedLpSupply.toString(), lpAccount.amount.toString());
+    const expectedFee = (BigInt(30_000) * lpAccount.amount) / lpSupply;
+    assert.equal(poolState.unclaimedUsdcFees.toString(), expectedFee.toString());
+    assert.ok(expectedFee < BigInt(30_000));
+
+    const before = await getAccount(provider.connection, accountA, "confirmed");
+    await program.methods.claimFees()
+      .accountsPartial({
+        signer: lp.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        poolStateAccount: pool,
+        feeCheckpoint: checkpoint,
+        lpAccount: lpAta,
+        usdcVaultAccount: vaultA,
+        wsolVaultAccount: vaultB,
+        userUsdcAccount: accountA,
+        userWsolAccount: accountB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([lp])
+      .rpc({ commitment: "confirmed" });
+    const after = await getAccount(provider.connection, accountA, "confirmed");
+
+    // The only lp gets the whole fee less rounding, lp tokens are untouched
+    const claimed = Number(after.amount - before.amount);
+    assert.ok(claimed > 29_990 && claimed <= 30_000, `claimed ${claimed}`);
+    const lpAfter = await getAccount(provider.connection, lpAta, "confirmed");
+    assert.equal(lpAfter.amount, lpAccount.amount);
+  });
+
+  it("Mint the protocol fee on the next liquidity event", async () => {
+    const treasury = Keypair.generate();
+    const { wallet: lp, mintA, mintB, accountA, accountB } = await newPairWallet(1_000_000_000_000, 1_000_000_000_000);
+    const { pool, usdcVault: vaultA, wsolVault: vaultB, lpMint } = poolAccountsFor(mintA, mintB);
+    const lpAta = getAssociatedTokenAddressSync(lpMint, lp.publicKey);
+
+    await initializePool(lp, mintA, mintB);
+
+    const feeAccount = await createAssociatedTokenAccount(provider.connection, lp, lpMint, treasury.publicKey);
+    // Only the config admin can turn the protocol fee on, not the pool creator
+    try {
+      await program.methods.setProtocolFee()
+        .accountsPartial({
+          admin: lp.publicKey,
+          poolStateAccount: pool,
+          protocolFeeAccount: feeAccount,
+        })
+        .signers([lp])
+        .rpc({ commitment: "confirmed" });
+      assert.fail("Pool creator should not set the protocol fee");
+    } catch (error) {
+      assert.include(error.toString(), "Unauthorized");
+    }
+
+    await program.methods.setProtocolFee()
+      .accountsPartial({
+        admin: provider.wallet.publicKey,
+        poolStateAccount: pool,
+        protocolFeeAccount: feeAccount,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const provide = () =>
+      program.methods.providelp(new BN(100_000_000_000), new BN(100_000_000))
+        .accountsPartial({
+          signer: lp.publicKey,
+          beneficiary: lp.publicKey,
+          payer: lp.publicKey,
+          usdcMint: mintA,
+          wsolMint: mintB,
+          userUsdcAccount: accountA,
+          userWsolAccount: accountB,
+          usdcVaultAccount: vaultA,
+          wsolVaultAccount: vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          lptokenmint: lpMint,
+          lpAta: lpAta,
+          mintAuthority: pool,
+          protocolFeeAccount: feeAccount,
+        })
+        .signers([lp])
+        .rpc({ commitment: "confirmed" });
+
+    // The first deposit only records k
+    await provide();
+    let poolState = await program.account.lpPoolAccountShape.fetch(pool);
+    assert.ok(poolState.kLast.gtn(0), "k_last should be recorded");
+    assert.equal((await getAccount(provider.connection, feeAccount, "confirmed")).amount, BigInt(0));
+
+    await program.methods.swap(new BN(10_000_000_000))
+      .accountsPartial({
+        signer: lp.publicKey,
+        inputMint: mintA,
+        outputMint: mintB,
+        poolStateaccount: pool,
+        inputVaultAccount: vaultA,
+        outputVaultAccount: vaultB,
+        userInputAccount: accountA,
+        userOutputAccount: accountB,
+        lpTokenMint: lpMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([lp])
+      .rpc({ commitment: "confirmed" });
+
+    // Swaps grow k without minting, the next deposit mints the protocol share
+    assert.equal((await getAccount(provider.connection, feeAccount, "confirmed")).amount, BigInt(0));
+    await provide();
+    const feeBalance = await getAccount(provider.connection, feeAccount, "confirmed");
+    assert.ok(feeBalance.amount > BigInt(0), "Protocol fee should be minted");
+  });
+
+  it("Donations do not move the reserves until sync, skim returns them", async () => {
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userWsolAccount = await getAssociatedTokenAddress(wsolMintPubkey, user_keypair.publicKey);
+    const donation = 1_000_000;
+
+    const before = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    await transfer(provider.connection, user_keypair, userUsdcAccount, usdcVaultPda, user_keypair, donation);
+    let poolState = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    assert.equal(poolState.usdcReserve.toString(), before.usdcReserve.toString());
+
+    // Skim sends the donation back out
+    const usdcBefore = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+    await program.methods.skim()
+      .accountsPartial({
+        poolStateAccount: poolStatePda,
+        usdcMint: usdcMintPubkey,
+        wsolMint: wsolMintPubkey,
+        usdcVaultAccount: usdcVaultPda,
+        wsolVaultAccount: wsolVaultPda,
+        recipientUsdcAccount: userUsdcAccount,
+        recipientWsolAccount: userWsolAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+    const usdcAfter = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+    assert.equal(usdcAfter.amount - usdcBefore.amount, BigInt(donation));
+
+    // Sync takes the donation into the reserves
+    await transfer(provider.connection, user_keypair, userUsdcAccount, usdcVaultPda, user_keypair, donation);
+    await program.methods.sync()
+      .accountsPartial({
+        poolStateAccount: poolStatePda,
+        usdcVaultAccount: usdcVaultPda,
+        wsolVaultAccount: wsolVaultPda,
+      })
+      .rpc({ commitment: "confirmed" });
+    poolState = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    assert.equal(
+      poolState.usdcReserve.toString(),
+      before.usdcReserve.addn(donation).toString()
+    );
+  });
+
+  it("First depositor can not inflate the lp price with a donation", async () => {
+    const {
+      wallet: attacker,
+      mintA,
+      mintB,
+      accountA: attackerA,
+      accountB: attackerB,
+    } = await newPairWallet(10_000_000_000, 10_000_000_000, 6);
+    const victim = await newWallet();
+    const { accountA: victimA, accountB: victimB } = await fundPair(attacker, { mintA, mintB }, victim, 10_000_000_000, 10_000_000_000);
+
+    const { pool, usdcVault: vaultA, wsolVault: vaultB, lpMint, lockedLp } = poolAccountsFor(mintA, mintB);
+    const lpAtaOf = (wallet: Keypair) => getAssociatedTokenAddressSync(lpMint, wallet.publicKey);
+
+    await initializePool(attacker, mintA, mintB);
+
+    const provide = (wallet: Keypair, accountA: PublicKey, accountB: PublicKey, amount: number) =>
+      program.methods.providelp(new BN(amount), new BN(amount))
+        .accountsPartial({
+          signer: wallet.publicKey,
+          beneficiary: wallet.publicKey,
+          payer: wallet.publicKey,
+          usdcMint: mintA,
+          wsolMint: mintB,
+          userUsdcAccount: accountA,
+          userWsolAccount: accountB,
+          usdcVaultAccount: vaultA,
+          wsolVaultAccount: vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          lptokenmint: lpMint,
+          lpAta: lpAtaOf(wallet),
+          mintAuthority: pool,
+          lockedLpAccount: lockedLp,
+        })
+        .signers([wallet])
+        .rpc({ commitment: "confirmed" });
+
+    // A first deposit at or below the minimum liquidity is rejected
+    try {
+      await provide(attacker, attackerA, attackerB, 1_000);
+      assert.fail("Deposit below the minimum liquidity should fail");
+    } catch (error) {
+      assert.include(error.toString(), "LiquidityTooLow");
+    }
+
+    // Smallest first deposit, the minimum liquidity is minted to the locked account
+    await provide(attacker, attackerA, attackerB, 1_001);
+    assert.equal((await getAccount(provider.connection, lockedLp, "confirmed")).amount, BigInt(1_000));
+    assert.equal((await getMint(provider.connection, lpMint, "confirmed")).supply, BigInt(1_001));
+
+    // A plain donation does not move the reserves the next deposit is priced against
+    const donation = 1_000_000_000;
+    await transfer(provider.connection, attacker, attackerA, vaultA, attacker, donation);
+    await transfer(provider.connection, attacker, attackerB, vaultB, attacker, donation);
+    await provide(victim, victimA, victimB, 1_001_000);
+    assert.equal((await getAccount(provider.connection, lpAtaOf(victim), "confirmed")).amount, BigInt(1_001_000));
+
+    // Synced into the reserves, the donation mostly goes to the other lps
+    await program.methods.sync()
+      .accountsPartial({ poolStateAccount: pool, usdcVaultAccount: vaultA, wsolVaultAccount: vaultB })
+      .rpc({ commitment: "confirmed" });
+    await provide(victim, victimA, victimB, 100_000_000);
+
+    const poolState = await program.account.lpPoolAccountShape.fetch(pool);
+    const supply = (await getMint(provider.connection, lpMint, "confirmed")).supply;
+    const valueOf = async (wallet: Keypair) => {
+      const lp = (await getAccount(provider.connection, lpAtaOf(wallet), "confirmed")).amount;
+      return (lp * BigInt(poolState.usdcReserve.toString())) / supply;
+    };
+    assert.ok((await valueOf(attacker)) < BigInt(donation), "Donation should cost the attacker");
+    assert.ok((await valueOf(victim)) >= BigInt(101_001_000 - 2), "Victim should keep the deposits");
+  });
+
+  it("Create a pool with its first deposit in one instruction", async () => {
+    const pair = await newPairWallet(1_000_000_000, 1_000_000_000_000);
+    const { pool, lpMint, lockedLp } = poolAccountsFor(pair.mintA, pair.mintB);
+    const lpAta = getAssociatedTokenAddressSync(lpMint, pair.wallet.publicKey);
+
+    // 100 USDC against 1 SOL, the creator picks the starting price
+    await initializePoolWithLiquidity(pair, 100_000_000, 1_000_000_000);
+
+    const poolState = await program.account.lpPoolAccountShape.fetch(pool);
+    assert.equal(poolState.usdcReserve.toString(), "100000000");
+    assert.equal(poolState.wsolReserve.toString(), "1000000000");
+
+    // sqrt(100_000_000 * 1_000_000_000), less the locked minimum liquidity
+    const lpBalance = await getAccount(provider.connection, lpAta, "confirmed");
+    assert.equal(lpBalance.amount, BigInt(316_227_766 - 1_000));
+    assert.equal((await getAccount(provider.connection, lockedLp, "confirmed")).amount, BigInt(1_000));
+  });
+
+  it("One pair can have a pool per fee tier", async () => {
+    const creator = await newWallet();
+    const { mintA, mintB } = await newPair(creator, 6);
+    const createPool = (feeTierBps: number) => initializePool(creator, mintA, mintB, { compound: {} }, feeTierBps);
+
+    // Stable flow and volatile flow on the same pair
+    for (const feeTierBps of [5, 100]) {
+      await createPool(feeTierBps);
+      const { pool } = poolAccountsFor(mintA, mintB, feeTierBps);
+      const poolState = await program.account.lpPoolAccountShape.fetch(pool);
+      assert.equal(poolState.feeTierBps, feeTierBps);
+    }
+
+    // Tiers outside the config are rejected
+    try {
+      await createPool(7);
+      assert.fail("Fee tier outside the config should fail");
+    } catch (error) {
+      assert.include(error.toString(), "FeeTierNotAllowed");
+    }
+  });
+
+  it("Migrate liquidity to another fee tier of the same pair", async () => {
+    const pair = await newPairWallet(1_000_000_000, 1_000_000_000_000);
+    const { wallet: creator, mintA, mintB, accountA, accountB } = pair;
+    const poolFor = (feeTierBps: number) => {
+      const accounts = poolAccountsFor(mintA, mintB, feeTierBps);
+      return { ...accounts, lpAta: getAssociatedTokenAddressSync(accounts.lpMint, creator.publicKey) };
+    };
+    const source = poolFor(FEE_TIER_BPS);
+    const target = poolFor(100);
+
+    // Seeded source pool and an empty pool on the new tier
+    await initializePoolWithLiquidity(pair, 100_000_000, 1_000_000_000);
+    await initializePool(creator, mintA, mintB, { compound: {} }, 100);
+
+    const migrate = (burnAmount: BN, minLpOut: BN, to = target) =>
+      program.methods.migrateLiquidity(burnAmount, new BN(1), new BN(1), minLpOut)
+        .accountsPartial({
+          signer: creator.publicKey,
+          payer: creator.publicKey,
+          usdcMint: mintA,
+          wsolMint: mintB,
+          userUsdcAccount: accountA,
+          userWsolAccount: accountB,
+          sourcePool: source.pool,
+          sourceUsdcVault: source.usdcVault,
+          sourceWsolVault: source.wsolVault,
+          sourceLpMint: source.lpMint,
+          sourceLpAccount: source.lpAta,
+          targetPool: to.pool,
+          targetUsdcVault: to.usdcVault,
+          targetWsolVault: to.wsolVault,
+          targetLpMint: to.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+
+    const lpBefore = (await getAccount(provider.connection, source.lpAta, "confirmed")).amount;
+    const burnAmount = new BN((lpBefore / BigInt(2)).toString());
+
+    // The minimum lp bound is checked before anything moves
+    try {
+      await migrate(burnAmount, new BN("18446744073709551615"));
+      assert.fail("Migration below the minimum lp should fail");
+    } catch (error) {
+      assert.include(error.toString(), "SlippageExceeded");
+    }
+
+    const usdcBefore = (await getAccount(provider.connection, accountA, "confirmed")).amount;
+    await migrate(burnAmount, new BN(1));
+
+    // The empty target takes everything vault to vault, the wallet balance is untouched
+    assert.equal((await getAccount(provider.connection, accountA, "confirmed")).amount, usdcBefore);
+    assert.equal((await getAccount(provider.connection, source.lpAta, "confirmed")).amount, lpBefore - BigInt(burnAmount.toString()));
+
+    const sourceState = await program.account.lpPoolAccountShape.fetch(source.pool);
+    const targetState = await program.account.lpPoolAccountShape.fetch(target.pool);
+    assert.equal(sourceState.usdcReserve.add(targetState.usdcReserve).toString(), "100000000");
+    assert.equal(sourceState.wsolReserve.add(targetState.wsolReserve).toString(), "1000000000");
+    assert.equal((await getAccount(provider.connection, target.usdcVault, "confirmed")).amount.toString(), targetState.usdcReserve.toString());
+
+    // First deposit into the target, the locked minimum is kept back there too
+    const targetLp = (await getAccount(provider.connection, target.lpAta, "confirmed")).amount;
+    assert.ok(targetLp > BigInt(0), "Should have received target lp");
+
+    // A seeded target at half the wsol per usdc only takes the wsol its ratio needs
+    const seeded = poolFor(5);
+    await initializePoolWithLiquidity(pair, 10_000_000, 50_000_000, 5);
+    const sourceBefore = await program.account.lpPoolAccountShape.fetch(source.pool);
+    const usdcBeforeSeeded = (await getAccount(provider.connection, accountA, "confirmed")).amount;
+    const wsolBeforeSeeded = (await getAccount(provider.connection, accountB, "confirmed")).amount;
+    const lpLeft = (await getAccount(provider.connection, source.lpAta, "confirmed")).amount;
+    await migrate(new BN((lpLeft / BigInt(2)).toString()), new BN(1), seeded);
+
+    const sourceAfter = await program.account.lpPoolAccountShape.fetch(source.pool);
+    const usdcOut = sourceBefore.usdcReserve.sub(sourceAfter.usdcReserve);
+    const wsolOut = sourceBefore.wsolReserve.sub(sourceAfter.wsolReserve);
+    const seededState = await program.account.lpPoolAccountShape.fetch(seeded.pool);
+    assert.equal(seededState.usdcReserve.toString(), usdcOut.addn(10_000_000).toString());
+    assert.equal(seededState.wsolReserve.toString(), seededState.usdcReserve.muln(5).toString());
+
+    // The wsol above the target ratio is returned to the user
+    const wsolRefund = wsolOut.sub(usdcOut.muln(5));
+    assert.ok(wsolRefund.gtn(0), "Should have returned wsol");
+    assert.equal((await getAccount(provider.connection, accountA, "confirmed")).amount, usdcBeforeSeeded);
+    assert.equal((await getAccount(provider.connection, accountB, "confirmed")).amount, wsolBeforeSeeded + BigInt(wsolRefund.toString()));
+  });
+
+  it("New pools start on the current layout version", async () => {
+    const poolState = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    assert.equal(poolState.version, 1);
+
+    // Only pools written by an older layout have anything to migrate
+    try {
+      await program.methods.migratePool()
+        .accounts({
+          payer: provider.wallet.publicKey,
+          poolStateAccount: poolStatePda,
+          usdcVaultAccount: usdcVaultPda,
+          wsolVaultAccount: wsolVaultPda,
+          lpMint: lpMintPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" });
+      assert.fail("Migrating a current pool should fail");
+    } catch (error) {
+      assert.include(error.toString(), "AlreadyCurrent");
+    }
+  });
+
+  it("Close a drained pool and reclaim the rent", async () => {
+    const pair = await newPairWallet(1_000_000_000, 1_000_000_000_000);
+    const { wallet: creator, mintA, mintB, accountA, accountB } = pair;
+    const { pool, usdcVault, wsolVault, lpMint } = poolAccountsFor(mintA, mintB);
+    const lpAta = getAssociatedTokenAddressSync(lpMint, creator.publicKey);
+
+    const registryPage = await nextRegistryPage();
+    await initializePoolWithLiquidity(pair, 100_000_000, 1_000_000_000, FEE_TIER_BPS, registryPage);
+
+    const closePool = () =>
+      program.methods.closePool()
+        .accountsPartial({
+          authority: creator.publicKey,
+          creator: creator.publicKey,
+          registryPage,
+          poolStateAccount: pool,
+          usdcMint: mintA,
+          wsolMint: mintB,
+          usdcVaultAccount: usdcVault,
+          wsolVaultAccount: wsolVault,
+          lpMint,
+          recipientUsdcAccount: accountA,
+          recipientWsolAccount: accountB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+
+    // Outstanding lp keeps the pool open
+    try {
+      await closePool();
+      assert.fail("Closing a pool with outstanding lp should fail");
+    } catch (error) {
+      assert.include(error.toString(), "PoolNotEmpty");
+    }
+
+    // Withdraw every lp token but the locked minimum
+    const lpBalance = await getAccount(provider.connection, lpAta, "confirmed");
+    await program.methods.removeLiquidity(new BN(lpBalance.amount.toString()))
+      .accounts({
+        signer: creator.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        userUsdcAccount: accountA,
+        userWsolAccount: accountB,
+        usdcVaultAccount: usdcVault,
+        wsolVaultAccount: wsolVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        poolStateAccount: pool,
+        lpMint,
+        userLpAta: lpAta,
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+
+    // The locked minimum's share of the reserves goes back with the rent
+    const lamportsBefore = await provider.connection.getBalance(creator.publicKey, "confirmed");
+    await closePool();
+    const lamportsAfter = await provider.connection.getBalance(creator.publicKey, "confirmed");
+    assert.ok(lamportsAfter > lamportsBefore, "Rent should be returned to the creator");
+
+    assert.isNull(await provider.connection.getAccountInfo(pool, "confirmed"));
+    assert.isNull(await provider.connection.getAccountInfo(usdcVault, "confirmed"));
+    assert.isNull(await provider.connection.getAccountInfo(wsolVault, "confirmed"));
+    assert.equal((await getAccount(provider.connection, accountA, "confirmed")).amount, BigInt(1_000_000_000));
+    assert.equal((await getAccount(provider.connection, accountB, "confirmed")).amount, BigInt(1_000_000_000_000));
+    const page = await program.account.poolRegistryPageAccountShape.fetch(registryPage);
+    assert.notOk(page.pools.some((entry) => entry.equals(pool)), "Closed pool should leave the registry");
+
+    // The empty lp mint is taken over, so the pair and fee tier can be created again
+    await initializePoolWithLiquidity(pair, 100_000_000, 1_000_000_000);
+    const recreated = await program.account.lpPoolAccountShape.fetch(pool);
+    assert.ok(recreated.lpTokenMint.equals(lpMint));
+  });
+
+  it("Close a claimable pool once every fee is claimed", async () => {
+    const amount = 1_000_000_000_000;
+    const { wallet: creator, mintA, mintB, accountA, accountB } = await newPairWallet(amount, amount);
+    const { pool, usdcVault, wsolVault, lpMint } = poolAccountsFor(mintA, mintB);
+    const [checkpoint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("fee_checkpoint_v3"), pool.toBuffer(), creator.publicKey.toBuffer()],
+      program.programId
+    );
+    const lpAta = getAssociatedTokenAddressSync(lpMint, creator.publicKey);
+
+    const registryPage = await nextRegistryPage();
+    await initializePool(creator, mintA, mintB, { claimable: {} });
+    await program.methods.openFeeCheckpoint()
+      .accountsPartial({
+        signer: creator.publicKey,
+        payer: creator.publicKey,
+        poolStateAccount: pool,
+        lpTokenMint: lpMint,
+        lpAta: lpAta,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+    await program.methods.providelp(new BN(100_000_000_000), new BN(100_000_000))
+      .accountsPartial({
+        signer: creator.publicKey,
+        beneficiary: creator.publicKey,
+        payer: creator.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        userUsdcAccount: accountA,
+        userWsolAccount: accountB,
+        usdcVaultAccount: usdcVault,
+        wsolVaultAccount: wsolVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        lptokenmint: lpMint,
+        lpAta: lpAta,
+        mintAuthority: pool,
+        feeCheckpoint: checkpoint,
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+
+    // Swaps in both directions leave fees that do not split evenly over the lp
+    for (const [inputMint, outputMint, inputVault, outputVault, input, output, amountIn] of [
+      [mintA, mintB, usdcVault, wsolVault, accountA, accountB, 10_000_001],
+      [mintB, mintA, wsolVault, usdcVault, accountB, accountA, 7_777_777],
+    ]) {
+      await program.methods.swap(new BN(amountIn))
+        .accountsPartial({
+          signer: creator.publicKey,
+          inputMint,
+          outputMint,
+          poolStateaccount: pool,
+          inputVaultAccount: inputVault,
+          outputVaultAccount: outputVault,
+          userInputAccount: input,
+          userOutputAccount: output,
+          lpTokenMint: lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    await program.methods.claimFees()
+      .accountsPartial({
+        signer: creator.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        poolStateAccount: pool,
+        feeCheckpoint: checkpoint,
+        lpAccount: lpAta,
+        usdcVaultAccount: usdcVault,
+        wsolVaultAccount: wsolVault,
+        userUsdcAccount: accountA,
+        userWsolAccount: accountB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+
+    const lpBalance = await getAccount(provider.connection, lpAta, "confirmed");
+    await program.methods.removeLiquidity(new BN(lpBalance.amount.toString()))
+      .accountsPartial({
+        signer: creator.publicKey,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        userUsdcAccount: accountA,
+        userWsolAccount: accountB,
+        usdcVaultAccount: usdcVault,
+        wsolVaultAccount: wsolVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        poolStateAccount: pool,
+        lpMint,
+        userLpAta: lpAta,
+        feeCheckpoint: checkpoint,
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+
+    // Nothing is owed to the checkpoint, what is left unclaimed is rounding dust
+    const drained = await program.account.lpPoolAccountShape.fetch(pool);
+    assert.equal(drained.owedUsdcFees.toString(), "0");
+    assert.equal(drained.owedWsolFees.toString(), "0");
+
+    await program.methods.closePool()
+      .accountsPartial({
+        authority: creator.publicKey,
+        creator: creator.publicKey,
+        registryPage,
+        poolStateAccount: pool,
+        usdcMint: mintA,
+        wsolMint: mintB,
+        usdcVaultAccount: usdcVault,
+        wsolVaultAccount: wsolVault,
+        lpMint,
+        recipientUsdcAccount: accountA,
+        recipientWsolAccount: accountB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([creator])
+      .rpc({ commitment: "confirmed" });
+
+    // The dust went to the creator with the locked minimum's reserves, every token is back
+    assert.isNull(await provider.connection.getAccountInfo(pool, "confirmed"));
+    assert.equal((await getAccount(provider.connection, accountA, "confirmed")).amount, BigInt(amount));
+    assert.equal((await getAccount(provider.connection, accountB, "confirmed")).amount, BigInt(amount));
+  });
+
+  it("Remove liquidity into USDC only", async () => {
+    const poolStateAccount = await program.account.lpPoolAccountShape.fetch(poolStatePda);
+    const lpMintPubkey = poolStateAccount.lpTokenMint;
+
+    const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
+    const userWsolAccount = await getAssociatedTokenAddress(wsolMintPubkey, user_keypair.publicKey);
+    const lpAtaPda = getAssociatedTokenAddressSync(lpMintPubkey, user_keypair.publicKey);
+
+    const lpBefore = await getAccount(provider.connection, lpAtaPda, "confirmed");
+    const usdcBefore = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+    const wsolBefore = await getAccount(provider.connection, userWsolAccount, "confirmed");
+
+    const burnAmount = new BN((lpBefore.amount / BigInt(10)).toString());
+
+    const tx = await program.methods.removeLiquiditySingle(burnAmount, usdcMintPubkey, new BN(1))
+      .accounts({
+        signer: user_keypair.publicKey,
+        usdcMint: usdcMintPubkey,
+        wsolMint: wsolMintPubkey,
+        userUsdcAccount: userUsdcAccount,
+        userWsolAccount: userWsolAccount,
+        usdcVaultAccount: usdcVaultPda,
+        wsolVaultAccount: wsolVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        poolStateAccount: poolStatePda,
+        lpMint: lpMintPubkey,
+        userLpAta: lpAtaPda,
+      })
+      .signers([user_keypair])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("✅ Single token remove liquidity tx:", tx);
+
+    const usdcAfter = await getAccount(provider.connection, userUsdcAccount, "confirmed");
+    const wsolAfter = await getAccount(provider.connection, userWsolAccount, "confirmed");
+    assert.ok(usdcAfter.amount > usdcBefore.amount, "Should have received USDC");
+    assert.equal(wsolAfter.amount, wsolBefore.amount, "WSOL balance should be untouched");
+  });
+
   it("Remove liquidity", async () => {
     // Fetch pool state to get LP mint
     const poolStateAccount = await program.account.lpPoolAccountShape.fetch(poolStatePda);
@@ -262,9 +1643,8 @@ describe("spool-amm", () => {
     const userUsdcAccount = await getAssociatedTokenAddress(usdcMintPubkey, user_keypair.publicKey);
     const userWsolAccount = await getAssociatedTokenAddress(wsolMintPubkey, user_keypair.publicKey);
 
-    // LP ATA seed (v3 suffix)
-    const lpAtaSeed = [Buffer.from("lptokenata_v3"), user_keypair.publicKey.toBuffer()];
-    const [lpAtaPda] = PublicKey.findProgramAddressSync(lpAtaSeed, program.programId);
+    // LP ATA
+    const lpAtaPda = getAssociatedTokenAddressSync(lpMintPubkey, user_keypair.publicKey);
 
     // Check LP balance
     let lpBalance;